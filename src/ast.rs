@@ -1,14 +1,8 @@
-use std::{
-    collections::HashMap,
-    ops::{Add, Div, Mul, Not, Rem, Sub},
-};
+use std::collections::HashMap;
 
-use serde::{
-    ser::{SerializeMap, SerializeSeq},
-    Serialize,
-};
+use serde::{ser::SerializeMap, Serialize};
 
-use crate::lexer::{TokenContentType, TokenType};
+use crate::lexer::{Span, TokenContentType, TokenType};
 
 #[derive(Debug, Clone)]
 pub struct Literal {
@@ -33,42 +27,120 @@ impl Array {
     }
 }
 
+/**
+ * A function parameter, with the expression it defaults to when a call leaves it out. A rest
+ * parameter (`...name`) is always the last one and collects any extra arguments into an array.
+ */
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub default: Option<Ast>,
+    pub rest: bool,
+}
+
+impl Param {
+    pub fn from(name: String) -> Param {
+        Param {
+            name,
+            default: None,
+            rest: false,
+        }
+    }
+}
+
+impl Serialize for Param {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_map(Some(4))?;
+        let r#type = "Param";
+        state.serialize_entry("type", &r#type)?;
+        state.serialize_entry("name", &self.name)?;
+        state.serialize_entry("default", &self.default)?;
+        state.serialize_entry("rest", &self.rest)?;
+        state.end()
+    }
+}
+
 // TODO: lowkey should probably rename this to AstNode
 #[derive(Debug, Clone)]
 pub enum Ast {
     Literal(Literal),
     Array(Array),
     /**
-     * name, value
+     * name, value, span of the name
      */
-    Var(String, Option<Box<Ast>>),
-    Binary(Box<Ast>, TokenType, Box<Ast>),
+    Var(String, Option<Box<Ast>>, Span),
     /**
-     * name, params, body
+     * left, operator, right, span of the operator
      */
-    Func(String, Vec<String>, Vec<Ast>),
+    Binary(Box<Ast>, TokenType, Box<Ast>, Span),
+    /**
+     * name, params, body, doc comment
+     */
+    Func(String, Vec<Param>, Vec<Ast>, Option<String>),
+    /**
+     * params, body: an anonymous `func (...) { ... }` expression
+     */
+    Lambda(Vec<Param>, Vec<Ast>),
     Return(Box<Ast>),
     /**
-     * id, range, body
+     * id, range, body, label, span of the `loop` keyword
+     */
+    For(String, Vec<Ast>, Vec<Ast>, Option<String>, Span),
+    /**
+     * condition, body, label, span of the `while` keyword
+     */
+    While(Box<Ast>, Vec<Ast>, Option<String>, Span),
+    /**
+     * label of the loop to leave, the innermost one if None
      */
-    For(String, Vec<Ast>, Vec<Ast>),
+    Break(Option<String>),
     /**
-     * condition, body
+     * label of the loop to continue, the innermost one if None
      */
-    While(Box<Ast>, Vec<Ast>),
+    Continue(Option<String>),
     /**
      * condition, if body, else body
      */
     Conditional(Box<Ast>, Vec<Ast>, Vec<Ast>),
-    Set(String, String, Box<Ast>),
-    Struct(String, Vec<String>),
-    Instance(String, HashMap<String, Ast>),
-    Call(Box<Ast>, Vec<Ast>),
-    Get(Box<Ast>, Box<Ast>, bool),
-    PointGet(Box<Ast>, String),
-    Unary(TokenType, Box<Ast>),
-    None,
-    // result of setting up a closure
+    /**
+     * target (a variable, or a chain of fields and indexes on one), value
+     */
+    Assign(Box<Ast>, Box<Ast>),
+    /**
+     * target, binary operator, value: `target op= value`
+     */
+    CompoundAssign(Box<Ast>, TokenType, Box<Ast>),
+    /**
+     * name, members, doc comment
+     */
+    Struct(String, Vec<String>, Option<String>),
+    /**
+     * struct name, members, span of the struct name
+     */
+    Instance(String, HashMap<String, Ast>, Span),
+    /**
+     * callee, positional args, named args (`name: value`), span of the whole call
+     */
+    Call(Box<Ast>, Vec<Ast>, Vec<(String, Ast)>, Span),
+    /**
+     * object, property, whether the property is an `[expr]` rather than a `.name`, span
+     */
+    Get(Box<Ast>, Box<Ast>, bool, Span),
+    /**
+     * operator, operand, span of the operator
+     */
+    Unary(TokenType, Box<Ast>, Span),
+    /**
+     * text and expression parts of an interpolated string, in order
+     */
+    Interpolation(Vec<Ast>),
+    /**
+     * array, span of the `...`: an array's items spread into an array literal or a call
+     */
+    Spread(Box<Ast>, Span),
 }
 
 impl Serialize for Ast {
@@ -82,74 +154,99 @@ impl Serialize for Ast {
                 let r#type = "Array";
                 stateMap.serialize_entry("type", &r#type)?;
                 stateMap.serialize_entry("value", &array.content)?;
-                return stateMap.end();
+                stateMap.end()
             }
             Ast::Literal(literal) => {
                 let mut state = serializer.serialize_map(Some(2))?;
                 let r#type = "Literal";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("value", &literal.content)?;
-                return state.end();
+                state.end()
             }
-            Ast::Var(name, value) => {
+            Ast::Var(name, value, _) => {
                 let mut state = serializer.serialize_map(Some(3))?;
                 let r#type = "Var";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("name", &name)?;
                 state.serialize_entry("value", &value)?;
-                return state.end();
+                state.end()
                 // state.serialize_element(&r#type)?;
                 // state.serialize_element(&name)?;
                 // let value: Ast = *value.unwrap();
                 // state.serialize_element(&value)?;
                 // return state.end();
             }
-            Ast::Binary(left, op, right) => {
+            Ast::Binary(left, op, right, _) => {
                 let mut state = serializer.serialize_map(Some(4))?;
                 let r#type = "Binary";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("left", &left)?;
                 state.serialize_entry("operator", &op)?;
                 state.serialize_entry("right", &right)?;
-                return state.end();
+                state.end()
                 // let mut state = serializer.serialize_seq(Some(3))?;
                 // state.serialize_element(&left)?;
                 // state.serialize_element(&op)?;
                 // state.serialize_element(&right)?;
                 // return state.end();
             }
-            Ast::Func(name, params, body) => {
-                let mut state = serializer.serialize_map(Some(4))?;
+            Ast::Func(name, params, body, doc) => {
+                let mut state = serializer.serialize_map(Some(5))?;
                 let r#type = "Func";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("name", &name)?;
                 state.serialize_entry("params", &params)?;
                 state.serialize_entry("body", &body)?;
-                return state.end();
+                state.serialize_entry("doc", &doc)?;
+                state.end()
+            }
+            Ast::Lambda(params, body) => {
+                let mut state = serializer.serialize_map(Some(3))?;
+                let r#type = "Lambda";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("params", &params)?;
+                state.serialize_entry("body", &body)?;
+                state.end()
             }
             Ast::Return(expr) => {
                 let mut state = serializer.serialize_map(Some(2))?;
                 let r#type = "Return";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("value", &expr)?;
-                return state.end();
+                state.end()
             }
-            Ast::For(id, range, body) => {
-                let mut state = serializer.serialize_map(Some(3))?;
+            Ast::For(id, range, body, label, _) => {
+                let mut state = serializer.serialize_map(Some(5))?;
                 let r#type = "For";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("id", &id)?;
                 state.serialize_entry("range", &range)?;
                 state.serialize_entry("body", &body)?;
-                return state.end();
+                state.serialize_entry("label", &label)?;
+                state.end()
             }
-            Ast::While(condition, body) => {
-                let mut state = serializer.serialize_map(Some(3))?;
+            Ast::While(condition, body, label, _) => {
+                let mut state = serializer.serialize_map(Some(4))?;
                 let r#type = "While";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("condition", &condition)?;
                 state.serialize_entry("body", &body)?;
-                return state.end();
+                state.serialize_entry("label", &label)?;
+                state.end()
+            }
+            Ast::Break(label) => {
+                let mut state = serializer.serialize_map(Some(2))?;
+                let r#type = "Break";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("label", &label)?;
+                state.end()
+            }
+            Ast::Continue(label) => {
+                let mut state = serializer.serialize_map(Some(2))?;
+                let r#type = "Continue";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("label", &label)?;
+                state.end()
             }
             Ast::Conditional(condition, r#if, r#else) => {
                 let mut state = serializer.serialize_map(Some(4))?;
@@ -158,62 +255,85 @@ impl Serialize for Ast {
                 state.serialize_entry("condition", &condition)?;
                 state.serialize_entry("body", &r#if)?;
                 state.serialize_entry("otherwise", &r#else)?;
-                return state.end();
+                state.end()
             }
-            Ast::Set(caller, property, value) => {
+            Ast::Assign(target, value) => {
+                let mut state = serializer.serialize_map(Some(3))?;
+                let r#type = "Assign";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("target", &target)?;
+                state.serialize_entry("value", &value)?;
+                state.end()
+            }
+            Ast::CompoundAssign(target, op, value) => {
                 let mut state = serializer.serialize_map(Some(4))?;
-                let r#type = "Set";
+                let r#type = "CompoundAssign";
                 state.serialize_entry("type", &r#type)?;
-                state.serialize_entry("caller", &caller)?;
-                state.serialize_entry("property", &property)?;
+                state.serialize_entry("target", &target)?;
+                state.serialize_entry("op", &op)?;
                 state.serialize_entry("value", &value)?;
-                return state.end();
+                state.end()
             }
-            Ast::Struct(name, members) => {
-                let mut state = serializer.serialize_map(Some(3))?;
+            Ast::Struct(name, members, doc) => {
+                let mut state = serializer.serialize_map(Some(4))?;
                 let r#type = "Struct";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("name", &name)?;
                 state.serialize_entry("members", &members)?;
-                return state.end();
+                state.serialize_entry("doc", &doc)?;
+                state.end()
             }
-            Ast::Instance(name, members) => {
+            Ast::Instance(name, members, _) => {
                 let mut state = serializer.serialize_map(Some(3))?;
                 let r#type = "Instance";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("name", &name)?;
                 state.serialize_entry("members", &members)?;
-                return state.end();
+                state.end()
             }
-            Ast::Call(caller, args) => {
+            Ast::Call(caller, args, named, _) => {
                 /*
                  * TODO: make sure that this works when caller is an AST and not a primitive string
                  */
-                let mut state = serializer.serialize_map(Some(3))?;
+                let mut state = serializer.serialize_map(Some(4))?;
                 let r#type = "Call";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("caller", &caller)?;
                 state.serialize_entry("args", &args)?;
-                return state.end();
+                state.serialize_entry("named", &named)?;
+                state.end()
             }
-            Ast::Get(caller, property, is_expr) => {
+            Ast::Get(caller, property, is_expr, _) => {
                 let mut state = serializer.serialize_map(Some(4))?;
                 let r#type = "Get";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("caller", &caller)?;
                 state.serialize_entry("property", &property)?;
                 state.serialize_entry("isExpr", &is_expr)?;
-                return state.end();
+                state.end()
             }
-            Ast::Unary(operator, apply) => {
+            Ast::Unary(operator, apply, _) => {
                 let mut state = serializer.serialize_map(Some(3))?;
                 let r#type = "Unary";
                 state.serialize_entry("type", &r#type)?;
                 state.serialize_entry("operator", &operator)?;
                 state.serialize_entry("apply", &apply)?;
-                return state.end();
+                state.end()
+            }
+            Ast::Interpolation(parts) => {
+                let mut state = serializer.serialize_map(Some(2))?;
+                let r#type = "Interpolation";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("parts", &parts)?;
+                state.end()
+            }
+            Ast::Spread(array, _) => {
+                let mut state = serializer.serialize_map(Some(2))?;
+                let r#type = "Spread";
+                state.serialize_entry("type", &r#type)?;
+                state.serialize_entry("array", &array)?;
+                state.end()
             }
-            _ => unimplemented!(),
         }
     }
 }
@@ -223,336 +343,55 @@ impl From<Ast> for String {
         match ast {
             Ast::Literal(literal) => format!("{:?}", literal.content),
             Ast::Array(array) => format!("{:?}", array.content),
-            Ast::Var(name, value) => {
+            Ast::Var(name, value, _) => {
                 if let Some(value) = value {
                     format!("(var {:?} = {:?})", name, value)
                 } else {
                     format!("(var {:?}) = None", name)
                 }
             }
-            Ast::Binary(left, op, right) => {
+            Ast::Binary(left, op, right, _) => {
                 format!("({:?} {:?} {:?})", left, op, right)
             }
-            Ast::Func(name, params, body) => {
+            Ast::Func(name, params, body, _) => {
                 format!("(fn {:?} {:?} {:?})", name, params, body)
             }
+            Ast::Lambda(params, body) => format!("(lambda {:?} {:?})", params, body),
             Ast::Return(expr) => format!("(return {:?})", expr),
-            Ast::For(id, range, body) => {
-                format!("(for {:?} {:?} {:?})", id, range, body)
+            Ast::For(id, range, body, label, _) => {
+                format!("(for {:?} {:?} {:?} {:?})", id, range, body, label)
             }
-            Ast::While(condition, body) => {
-                format!("(while {:?} {:?})", condition, body)
+            Ast::While(condition, body, label, _) => {
+                format!("(while {:?} {:?} {:?})", condition, body, label)
             }
+            Ast::Break(label) => format!("(break {:?})", label),
+            Ast::Continue(label) => format!("(continue {:?})", label),
             Ast::Conditional(condition, if_body, else_body) => {
                 format!("(if {:?} {:?} {:?})", condition, if_body, else_body)
             }
-            Ast::Set(caller, property, value) => {
-                format!("(set {:?} {:?} {:?})", caller, property, value)
+            Ast::Assign(target, value) => {
+                format!("(assign {:?} {:?})", target, value)
+            }
+            Ast::CompoundAssign(target, op, value) => {
+                format!("(assign {:?} {:?}= {:?})", target, op, value)
             }
-            Ast::Struct(name, fields) => {
+            Ast::Struct(name, fields, _) => {
                 format!("(struct {:?} {:?})", name, fields)
             }
-            Ast::Instance(name, fields) => {
+            Ast::Instance(name, fields, _) => {
                 format!("(instance {:?} {:?})", name, fields)
             }
-            Ast::Call(caller, args) => {
-                format!("(call {:?} {:?})", caller, args)
+            Ast::Call(caller, args, named, _) => {
+                format!("(call {:?} {:?} {:?})", caller, args, named)
             }
-            Ast::Get(caller, property, is_method) => {
+            Ast::Get(caller, property, is_method, _) => {
                 format!("(get {:?} {:?} {:?})", caller, property, is_method)
             }
-            Ast::PointGet(caller, property) => {
-                format!("(point-get {:?} {:?})", caller, property)
-            }
-            Ast::Unary(op, expr) => {
+            Ast::Unary(op, expr, _) => {
                 format!("({:?} {:?})", op, expr)
             }
-            Ast::None => "None".to_string(),
-        }
-    }
-}
-
-impl Not for Ast {
-    type Output = Self;
-
-    fn not(self) -> Self::Output {
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Boolean(b) => Ast::Literal(Literal {
-                    content: TokenContentType::Boolean(!b),
-                }),
-                _ => panic!("Expected boolean literal but got {:?}", literal.content),
-            },
-            _ => panic!("Expected boolean literal but got {:?}", self),
-        }
-    }
-}
-
-impl Add for Ast {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self::Output {
-        // support strings and numbers
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => Ast::Literal(Literal {
-                            content: TokenContentType::Number(n + other_n),
-                        }),
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                TokenContentType::String(s) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::String(other_s) => Ast::Literal(Literal {
-                            content: TokenContentType::String(format!("{}{}", s, other_s)),
-                        }),
-                        _ => panic!(
-                            "Expected string literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected string literal but got {:?}", other),
-                },
-                _ => panic!(
-                    "Expected number or string literal but got {:?}",
-                    literal.content
-                ),
-            },
-            _ => panic!("Expected number or string literal but got {:?}", self),
-        }
-    }
-}
-
-impl Sub for Ast {
-    type Output = Self;
-
-    fn sub(self, other: Self) -> Self::Output {
-        // support strings and numbers
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => Ast::Literal(Literal {
-                            content: TokenContentType::Number(n - other_n),
-                        }),
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                _ => panic!(
-                    "Expected number or string literal but got {:?}",
-                    literal.content
-                ),
-            },
-            _ => panic!("Expected number or string literal but got {:?}", self),
-        }
-    }
-}
-
-impl Mul for Ast {
-    type Output = Self;
-
-    fn mul(self, other: Self) -> Self::Output {
-        // support strings and numbers
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => Ast::Literal(Literal {
-                            content: TokenContentType::Number(n * other_n),
-                        }),
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                TokenContentType::String(s) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => {
-                            if other_n.fract() != 0.0 {
-                                panic!("Cannot multiply string by non-integer number")
-                            }
-                            if other_n < 0.0 {
-                                panic!("Cannot multiply string by negative number")
-                            }
-                            let mut result = String::new();
-                            for _ in 0..(other_n as u64) {
-                                result.push_str(&s);
-                            }
-                            Ast::Literal(Literal {
-                                content: TokenContentType::String(result),
-                            })
-                        }
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                _ => panic!(
-                    "Expected number or string literal but got {:?}",
-                    literal.content
-                ),
-            },
-            _ => panic!("Expected number or string literal but got {:?}", self),
-        }
-    }
-}
-
-impl Div for Ast {
-    type Output = Self;
-
-    fn div(self, other: Self) -> Self::Output {
-        // support strings and numbers
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => {
-                            if other_n == 0.0 {
-                                panic!("Cannot divide by zero")
-                            }
-                            Ast::Literal(Literal {
-                                content: TokenContentType::Number(n / other_n),
-                            })
-                        }
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                _ => panic!(
-                    "Expected number or string literal but got {:?}",
-                    literal.content
-                ),
-            },
-            _ => panic!("Expected number or string literal but got {:?}", self),
-        }
-    }
-}
-
-impl Rem for Ast {
-    type Output = Self;
-
-    fn rem(self, other: Self) -> Self::Output {
-        // support strings and numbers
-        match self {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => {
-                            if other_n == 0.0 {
-                                panic!("Cannot get remainder by zero")
-                            }
-                            Ast::Literal(Literal {
-                                content: TokenContentType::Number(n % other_n),
-                            })
-                        }
-                        _ => panic!(
-                            "Expected number literal but got {:?}",
-                            other_literal.content
-                        ),
-                    },
-                    _ => panic!("Expected number literal but got {:?}", other),
-                },
-                _ => panic!(
-                    "Expected number or string literal but got {:?}",
-                    literal.content
-                ),
-            },
-            _ => panic!("Expected number or string literal but got {:?}", self),
-        }
-    }
-}
-
-impl PartialEq for Ast {
-    fn eq(&self, other: &Self) -> bool {
-        match self {
-            Ast::Literal(literal) => match literal.content.clone() {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => n == other_n,
-                        _ => false,
-                    },
-                    _ => false,
-                },
-                TokenContentType::String(s) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content.clone() {
-                        TokenContentType::String(other_s) => s == other_s,
-                        _ => false,
-                    },
-                    _ => false,
-                },
-                TokenContentType::Boolean(b) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Boolean(other_b) => b == other_b,
-                        _ => false,
-                    },
-                    _ => false,
-                },
-            },
-            Ast::Var(_name, value) => match other {
-                Ast::Var(_other_name, other_value) => value == other_value,
-                _ => false,
-            },
-            Ast::Array(array) => match other {
-                Ast::Array(other_array) => &array.content == &other_array.content,
-                _ => false,
-            },
-            _ => false,
-        }
-    }
-}
-
-impl PartialOrd for Ast {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self {
-            Ast::Literal(literal) => match literal.content.clone() {
-                TokenContentType::Number(n) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Number(other_n) => n.partial_cmp(&other_n),
-                        _ => None,
-                    },
-                    _ => None,
-                },
-                TokenContentType::String(s) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content.clone() {
-                        TokenContentType::String(other_s) => s.partial_cmp(&other_s),
-                        _ => None,
-                    },
-                    _ => None,
-                },
-                TokenContentType::Boolean(b) => match other {
-                    Ast::Literal(other_literal) => match other_literal.content {
-                        TokenContentType::Boolean(other_b) => b.partial_cmp(&other_b),
-                        _ => None,
-                    },
-                    _ => None,
-                },
-            },
-            Ast::Var(_name, value) => match other {
-                Ast::Var(_other_name, other_value) => value.partial_cmp(&other_value),
-                _ => None,
-            },
-            Ast::Array(array) => match other {
-                Ast::Array(other_array) => (&array.content).partial_cmp(&other_array.content),
-                _ => None,
-            },
-            _ => None,
+            Ast::Interpolation(parts) => format!("(interpolate {:?})", parts),
+            Ast::Spread(array, _) => format!("(spread {:?})", array),
         }
     }
 }
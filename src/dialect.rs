@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+/**
+ * A surface syntax for the language. Each dialect maps its own keywords onto the canonical
+ * ones the parser understands, so every dialect produces the same Ast. Keyword tokens carry
+ * the canonical word as their value; the spelling the user actually wrote is still available
+ * through the token's span. Identifiers are never rewritten: a dialect's builtin names are
+ * bound next to the canonical ones instead, so a user's own `ink` or `print` is just a name.
+ */
+#[derive(Debug, Clone)]
+pub struct Dialect {
+    pub name: &'static str,
+    // surface keyword -> canonical keyword
+    keywords: HashMap<&'static str, &'static str>,
+    // extra global name -> the builtin it is bound to
+    aliases: HashMap<&'static str, &'static str>,
+    /**
+     * source (in this dialect) run before the program, for builtins that can be written in the
+     * language itself
+     */
+    pub prelude: &'static str,
+    /**
+     * whether the `Canvas` drawing builtins are in scope
+     */
+    pub canvas: bool,
+}
+
+// the keywords every dialect has to provide a spelling for (`=` is punctuation and always lexed)
+const CANONICAL_KEYWORDS: [&str; 15] = [
+    "let", "struct", "prep", "has", "func", "needs", "finished", "loop", "through", "while", "if",
+    "elif", "else", "break", "continue",
+];
+
+impl Dialect {
+    pub fn classic() -> Dialect {
+        Dialect {
+            name: "classic",
+            keywords: CANONICAL_KEYWORDS.iter().map(|k| (*k, *k)).collect(),
+            aliases: HashMap::new(),
+            prelude: "",
+            canvas: false,
+        }
+    }
+
+    /**
+     * The creative-coding syntax: `prepare x as 1`, `sketch name needs (...)`,
+     * `brush Name has {...}`, `ink(...)` to print and `Canvas` to draw on.
+     */
+    pub fn sketch() -> Dialect {
+        let mut keywords: HashMap<&'static str, &'static str> =
+            CANONICAL_KEYWORDS.iter().map(|k| (*k, *k)).collect();
+        for (canonical, surface) in [("let", "prepare"), ("func", "sketch"), ("struct", "brush")] {
+            keywords.remove(canonical);
+            keywords.insert(surface, canonical);
+        }
+        keywords.insert("as", "=");
+
+        Dialect {
+            name: "sketch",
+            keywords,
+            aliases: HashMap::from([("ink", "print")]),
+            prelude: "brush Color has { r, g, b }",
+            canvas: true,
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Dialect> {
+        match name {
+            "classic" => Some(Dialect::classic()),
+            "sketch" => Some(Dialect::sketch()),
+            _ => None,
+        }
+    }
+
+    /**
+     * Looks for a `~ dialect: <name>` pragma among the comment lines at the top of a program.
+     */
+    pub fn pragma(program: &str) -> Option<&str> {
+        program
+            .lines()
+            .map(str::trim)
+            .take_while(|line| line.is_empty() || line.starts_with('~'))
+            .find_map(|line| {
+                line.trim_start_matches('~')
+                    .trim()
+                    .strip_prefix("dialect:")
+                    .map(str::trim)
+            })
+    }
+
+    pub fn keyword(&self, word: &str) -> Option<&'static str> {
+        self.keywords.get(word).copied()
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.aliases
+            .iter()
+            .map(|(alias, builtin)| (*alias, *builtin))
+    }
+}
+
+impl Default for Dialect {
+    fn default() -> Dialect {
+        Dialect::classic()
+    }
+}
@@ -0,0 +1,52 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::value::Value;
+
+/**
+ * One level of variables: the globals, a function call's frame or a block's locals. A lookup
+ * that misses here carries on in the enclosing environment, out to the globals.
+ */
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    parent: Option<Scope>,
+}
+
+pub type Scope = Rc<RefCell<Environment>>;
+
+impl Environment {
+    pub fn global() -> Scope {
+        Rc::new(RefCell::new(Environment::default()))
+    }
+
+    pub fn child(parent: &Scope) -> Scope {
+        Rc::new(RefCell::new(Environment {
+            values: HashMap::new(),
+            parent: Some(Rc::clone(parent)),
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.borrow().get(name),
+        }
+    }
+
+    // `let`: creates the variable in this environment, shadowing any outer one
+    pub fn declare(&mut self, name: String, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    // plain assignment: updates the variable where it was declared, false if it never was
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.parent {
+            Some(parent) => parent.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
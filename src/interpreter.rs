@@ -1,1131 +1,1177 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+};
 
 use crate::{
-    ast::{Array, Ast, Literal},
-    lexer::{TokenContentType, TokenType},
+    ast::{Ast, Literal},
+    environment::{Environment, Scope},
+    lexer::{Span, TokenContentType, TokenType},
+    value::{Function, Value},
 };
 
 pub struct Interpreter {}
 
-type Scope = Rc<RefCell<HashMap<String, Ast>>>;
-type FunctionScope = Rc<RefCell<HashMap<String, Box<dyn Fn(Vec<Ast>) -> Ast>>>>;
-type StructScope = Rc<RefCell<HashMap<String, HashMap<String, Ast>>>>; // this only stores the params each thing has, not the actual instances
-                                                                       // those go in the scope
+pub type StructScope = Rc<RefCell<HashMap<String, Vec<String>>>>; // this only stores the fields each struct has, not the actual instances
+                                                                  // those go in the scope
 
-impl Interpreter {
-    pub fn toPrint(
-        ast: Ast,
-        scope: Scope,
-        functionScope: FunctionScope,
-        structScope: StructScope,
-    ) -> String {
-        match ast {
-            Ast::Literal(literal) => match literal.content {
-                TokenContentType::String(s) => s,
-                TokenContentType::Number(n) => n.to_string(),
-                TokenContentType::Boolean(b) => b.to_string(),
-            },
-            Ast::Var(name, _) => {
-                if !Interpreter::inScope(scope.clone(), name.clone())
-                    && !Interpreter::isFuncInScope(functionScope.clone(), name.clone())
-                {
-                    panic!("Variable {} not found in scope", name);
-                }
-                if Interpreter::inScope(scope.clone(), name.clone()) {
-                    return Interpreter::toPrint(
-                        scope.borrow().get(&name).unwrap().clone(),
-                        scope.clone(),
-                        functionScope.clone(),
-                        structScope.clone(),
-                    );
-                } else if Interpreter::isFuncInScope(functionScope.clone(), name.clone()) {
-                    return format!("function {}", name);
-                } else {
-                    panic!("Variable {} not found in scope", name);
-                }
-            }
-            Ast::Array(array) => {
-                let mut result = "[".to_string();
-                for (i, item) in array.content.iter().enumerate() {
-                    result.push_str(&Interpreter::toPrint(
-                        item.clone(),
-                        scope.clone(),
-                        functionScope.clone(),
-                        structScope.clone(),
-                    ));
-                    if i != array.content.len() - 1 {
-                        result.push_str(", ");
-                    }
-                }
-                result.push_str("]");
-                return result;
+// methods every array has; they change the array they're called on, wherever it's shared
+// `add` is the sketch dialect's spelling of `push`
+const ARRAY_METHODS: [&str; 5] = ["push", "add", "pop", "reverse", "sort"];
+
+// how deeply script functions can call each other when `--max-depth` doesn't say otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// how many lines of a long backtrace are shown from its innermost and outermost ends
+const BACKTRACE_ENDS: usize = 10;
+
+// stack left free below the limit, for the Rust frames that run between two checks
+const STACK_RESERVE: usize = 1024 * 1024;
+
+thread_local! {
+    // the script function calls running right now, and how many are allowed at once
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    // the lowest stack address evaluation may reach, 0 until `setStackSize` says
+    static STACK_LIMIT: Cell<usize> = const { Cell::new(0) };
+}
+
+/**
+ * How a statement finished: normally, or with a `finished`, `break` or `continue` that the
+ * enclosing blocks hand outwards until the function or loop it belongs to handles it.
+ */
+#[derive(Debug, Clone)]
+pub enum ControlFlow {
+    Normal,
+    Return(Value),
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl ControlFlow {
+    // whether a `break`/`continue` aimed at `target` stops at the loop labeled `label`
+    fn reaches(target: &Option<String>, label: &Option<String>) -> bool {
+        target.is_none() || target == label
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // a value of the wrong type for what's done with it
+    Type,
+    // a variable or struct that was never declared
+    Name,
+    Index,
+    Property,
+    Arithmetic,
+    // the wrong number of arguments to a function
+    Argument,
+    Input,
+    // calls nested deeper than the maximum depth, usually recursion that never stops
+    StackOverflow,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ErrorKind::Type => "type error",
+            ErrorKind::Name => "name error",
+            ErrorKind::Index => "index error",
+            ErrorKind::Property => "property error",
+            ErrorKind::Arithmetic => "arithmetic error",
+            ErrorKind::Argument => "argument error",
+            ErrorKind::Input => "input error",
+            ErrorKind::StackOverflow => "stack overflow",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+// a call the error passed through on its way out: the function, and where it was called from
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub span: Option<Span>,
+}
+
+/**
+ * An error in a running program. It starts out with just a kind and message wherever the
+ * problem is noticed, and picks up the span of the nearest Ast node and the calls it leaves on
+ * the way out to `run`.
+ */
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    // innermost call first
+    pub call_stack: Vec<Frame>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> RuntimeError {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            span: None,
+            call_stack: vec![],
+        }
+    }
+
+    // where the error happened, unless somewhere more precise was already recorded
+    pub fn at(mut self, span: &Span) -> RuntimeError {
+        if self.span.is_none() {
+            self.span = Some(span.clone());
+        }
+        self
+    }
+
+    // records that the error came out of a call to `function`
+    fn inFunction(mut self, function: &str) -> RuntimeError {
+        self.call_stack.push(Frame {
+            function: function.to_string(),
+            span: None,
+        });
+        self
+    }
+
+    // records where the call it came out of was made
+    fn calledAt(mut self, span: &Span) -> RuntimeError {
+        if let Some(frame) = self.call_stack.last_mut() {
+            if frame.span.is_none() {
+                frame.span = Some(span.clone());
             }
-            Ast::Instance(name, members) => {
-                let mut result = format!("{} {{", name);
-                for (i, (field, value)) in members.iter().enumerate() {
-                    result.push_str(&format!(
-                        "{}: {}",
-                        field,
-                        Interpreter::toPrint(
-                            value.clone(),
-                            scope.clone(),
-                            functionScope.clone(),
-                            structScope.clone(),
-                        )
-                    ));
-                    if i != members.len() - 1 {
-                        result.push_str(", ");
-                    }
+        }
+        self.at(span)
+    }
+
+    /**
+     * One line per function the error passed through, innermost first, each with where that
+     * function had got to. Empty if it happened outside any function.
+     */
+    pub fn backtrace(&self) -> Vec<String> {
+        let location = |span: &Option<Span>| match span {
+            Some(span) => span.to_string(),
+            None => "<unknown>".to_string(),
+        };
+
+        let mut lines: Vec<String> = vec![];
+        let mut span = &self.span;
+        // runaway recursion repeats the same line hundreds of times, so repeats are counted
+        let mut repeats = 0;
+        for frame in &self.call_stack {
+            let line = format!("in {} at {}", frame.function, location(span));
+            if lines.last() == Some(&line) {
+                repeats += 1;
+            } else {
+                if repeats > 0 {
+                    lines.push(format!("... repeated {} more times", repeats));
+                    repeats = 0;
                 }
-                result.push_str("}");
-                return result;
+                lines.push(line);
             }
-            Ast::Binary(_, _, _) => {
-                let result = Interpreter::evaluate(
-                    Box::new(ast.clone()),
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-                // // println!("result: {:?}", result);
-                return Interpreter::toPrint(
-                    result,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-            }
-            Ast::Get(caller, property, isExpr) => {
-                let result = Interpreter::evaluate(
-                    Box::new(Ast::Get(caller, property, isExpr)),
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
+            span = &frame.span;
+        }
+        if repeats > 0 {
+            lines.push(format!("... repeated {} more times", repeats));
+        }
+        if !lines.is_empty() {
+            lines.push(format!("in <main> at {}", location(span)));
+        }
+        // recursion going back and forth between functions doesn't repeat line for line either
+        if lines.len() > 2 * BACKTRACE_ENDS {
+            let hidden = lines.len() - 2 * BACKTRACE_ENDS;
+            lines.splice(
+                BACKTRACE_ENDS..lines.len() - BACKTRACE_ENDS,
+                [format!("... {} more lines", hidden)],
+            );
+        }
+        lines
+    }
+}
 
-                return Interpreter::toPrint(
-                    result,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-            }
-            _ => {
-                panic!("Expected expression but got statement {:?}", ast);
-            }
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{}: {}: {}", span, self.kind, self.message),
+            None => write!(f, "{}: {}", self.kind, self.message),
         }
     }
+}
 
+impl Interpreter {
     pub fn run(
-        ast: Vec<Ast>,
+        ast: &[Ast],
         scope: Scope,
-        functionScope: FunctionScope,
         structScope: StructScope,
-    ) -> (Scope, Option<Ast>) {
-        let mut retScope = scope.clone();
-        // println!("scope in run: {:?}", retScope.clone());
-        // println!("nodes: {:?}", ast.clone());
+    ) -> Result<ControlFlow, RuntimeError> {
         for node in ast {
-            // println!("running node {:?}", node);
-            // println!("calling execute from run");
-            let (newScope, _ret) = Interpreter::execute(
-                node,
-                retScope.clone(),
-                functionScope.clone(),
-                structScope.clone(),
-            );
-
-            // println!();
-            // println!("new scope: {:?}", newScope.clone());
-            // println!();
-            // println!("ret: {:?}", _ret.clone());
-            retScope = newScope;
-            match _ret {
-                Some(ret) => match ret {
-                    Ast::Return(value) => {
-                        return (retScope, Some(*value));
-                    }
-                    _ => {}
-                },
-                None => {}
+            let flow = Interpreter::execute(node, scope.clone(), structScope.clone())?;
+            // `finished`, `break` and `continue` stop the block and are handed to whoever runs it
+            if !matches!(flow, ControlFlow::Normal) {
+                return Ok(flow);
             }
-            // if ret.is_some() {
-            //     return ret;
-            // }
         }
-        (retScope, None)
+        Ok(ControlFlow::Normal)
     }
 
-    fn inScope(scope: Scope, name: String) -> bool {
-        scope.borrow_mut().contains_key(&name)
+    fn isStructInScope(structScope: StructScope, name: &str) -> bool {
+        structScope.borrow().contains_key(name)
     }
 
-    fn isFuncInScope(functionScope: FunctionScope, name: String) -> bool {
-        let functionScope = functionScope.borrow();
-        functionScope.contains_key(&name)
+    // the span of an expression, for the nodes that have one
+    fn span(ast: &Ast) -> Option<&Span> {
+        match ast {
+            Ast::Var(_, _, span)
+            | Ast::Binary(_, _, _, span)
+            | Ast::Unary(_, _, span)
+            | Ast::Call(_, _, _, span)
+            | Ast::Get(_, _, _, span)
+            | Ast::Instance(_, _, span) => Some(span),
+            _ => None,
+        }
     }
 
-    fn isStructInScope(structScope: StructScope, name: String) -> bool {
-        structScope.borrow_mut().contains_key(&name)
+    // a type error about `ast`, placed at it, or at `fallback` when it has no span of its own
+    fn typeError(ast: &Ast, fallback: &Span, message: String) -> RuntimeError {
+        let span = Interpreter::span(ast).unwrap_or(fallback);
+        RuntimeError::new(ErrorKind::Type, message).at(span)
     }
 
-    pub fn evaluate(
-        value: Box<Ast>,
+    // evaluates array items or call arguments, splicing in the items of any `...array`
+    fn elements(
+        items: &[Ast],
         scope: Scope,
-        functionScope: FunctionScope,
         structScope: StructScope,
-    ) -> Ast {
-        // println!("evaluating boop {:?}", *value);
-        match *value {
-            Ast::Var(name, _) => {
-                // println!("found variable {}", name);
-                // println!("functionscope: {:#?}", functionScope.borrow_mut().keys());
-                let borrowed_scope = functionScope.borrow();
-                let iter = borrowed_scope.keys();
-                // println!("iter: {:#?}", iter);
-                // println!("scope: {:#?}", scope.borrow_mut().keys());
-                // println!("node: {:#?}", name);
-                // println!("functionscope: {:#?}", iter.clone().collect::<Vec<_>>());
-                if !Interpreter::inScope(scope.clone(), name.clone())
-                    && !iter.clone().any(|x| x == &name)
-                {
-                    panic!("Variable {} not found in scope", name);
-                }
-                if Interpreter::inScope(scope.clone(), name.clone()) {
-                    return scope.borrow_mut().get(&name).unwrap().clone();
-                } else if iter.clone().any(|x| x == &name) {
-                    // println!("found function {}", name);
-                    return Ast::Func(name, vec![], vec![]);
-                } else {
-                    panic!("Variable {} not found in scope", name);
-                }
-            }
-            Ast::Unary(operator, value) => {
-                let value = Interpreter::evaluate(
-                    value,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-                match operator {
-                    TokenType::Not => return !value,
-                    _ => {
-                        panic!("Unknown unary operator {:?}", operator);
+    ) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = vec![];
+        for item in items {
+            match item {
+                Ast::Spread(array, span) => {
+                    match Interpreter::evaluate(array, scope.clone(), structScope.clone())? {
+                        Value::Array(array) => values.extend(array.borrow().iter().cloned()),
+                        other => {
+                            return Err(RuntimeError::new(
+                                ErrorKind::Type,
+                                format!("Can only spread an array, not {}", other.typeName()),
+                            )
+                            .at(span))
+                        }
                     }
                 }
-            }
-            Ast::Binary(left, op, right) => {
-                let mut operations: HashMap<TokenType, Box<dyn Fn(Ast, Ast) -> Ast>> =
-                    HashMap::new();
-                operations.insert(TokenType::Plus, Box::new(|a, b| a + b));
-                operations.insert(TokenType::Minus, Box::new(|a, b| a - b));
-                operations.insert(TokenType::Asterisk, Box::new(|a, b| a * b));
-                operations.insert(TokenType::Slash, Box::new(|a, b| a / b));
-                operations.insert(TokenType::Modulo, Box::new(|a, b| a % b));
-                operations.insert(
-                    TokenType::Equiv,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a == b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::NotEquiv,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a != b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::Gt,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a > b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::Gte,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a >= b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::Lt,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a < b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::Lte,
-                    Box::new(|a, b| {
-                        Ast::Literal(Literal {
-                            content: (a <= b).into(),
-                        })
-                    }),
-                );
-                operations.insert(
-                    TokenType::And,
-                    Box::new(|a, b| match [a, b] {
-                        [Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(aBool),
-                        }), Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(bBool),
-                        })] => Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(aBool && bBool),
-                        }),
-                        _ => panic!("Expected boolean values for AND operation"),
-                    }),
-                );
-                operations.insert(
-                    TokenType::Or,
-                    Box::new(|a, b| match [a.clone(), b.clone()] {
-                        [Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(aBool),
-                        }), Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(bBool),
-                        })] => Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(aBool || bBool),
-                        }),
-                        _ => panic!("Expected boolean values for AND operation"),
-                    }),
-                );
-
-                let left = Interpreter::evaluate(
-                    left,
+                _ => values.push(Interpreter::evaluate(
+                    item,
                     scope.clone(),
-                    functionScope.clone(),
                     structScope.clone(),
-                );
-                let right = Interpreter::evaluate(
-                    right,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-
-                // // println!("left: {:?}, op: {:?}, right: {:?}", left, op, right);
+                )?),
+            }
+        }
+        Ok(values)
+    }
 
-                if !operations.contains_key(&op) {
-                    panic!("Unknown binary operator {:?}", op);
+    pub fn evaluate(
+        value: &Ast,
+        scope: Scope,
+        structScope: StructScope,
+    ) -> Result<Value, RuntimeError> {
+        Interpreter::checkStack()?;
+        match value {
+            Ast::Var(name, _, span) => scope.borrow().get(name).ok_or_else(|| {
+                RuntimeError::new(
+                    ErrorKind::Name,
+                    format!("Variable {} not found in scope", name),
+                )
+                .at(span)
+            }),
+            Ast::Unary(operator, value, span) => {
+                let value = Interpreter::evaluate(value, scope, structScope)?;
+                match operator {
+                    TokenType::Not => !value,
+                    TokenType::Minus => -value,
+                    TokenType::Plus => value.positive(),
+                    _ => unreachable!("the parser only builds !, - and + unary operators"),
                 }
-
-                let resultOfOp = operations.get(&op).unwrap()(left, right);
-                // // println!("result of op: {:?}", resultOfOp);
-
-                return resultOfOp;
+                .map_err(|error| error.at(span))
             }
-            Ast::Literal(literal) => Ast::Literal(literal),
-            Ast::Array(array) => Ast::Array(Array {
-                content: array
-                    .content
-                    .into_iter()
-                    .map(|x| {
-                        Interpreter::evaluate(
-                            Box::new(x.clone()),
-                            scope.clone(),
-                            functionScope.clone(),
-                            structScope.clone(),
+            Ast::Binary(left, op, right, span) => {
+                let left = Interpreter::evaluate(left, scope.clone(), structScope.clone())?;
+
+                // `&&` and `||` only evaluate their right side when it decides the result
+                if matches!(op, TokenType::And | TokenType::Or) {
+                    let operator = if *op == TokenType::And { "&&" } else { "||" };
+                    let notBoolean = |value: Value| {
+                        RuntimeError::new(
+                            ErrorKind::Type,
+                            format!(
+                                "Expected booleans for {} but got {}",
+                                operator,
+                                value.typeName()
+                            ),
                         )
-                    })
-                    .collect::<Vec<_>>(),
-            }),
-            Ast::Instance(name, members) => {
-                if !Interpreter::isStructInScope(structScope.clone(), name.clone()) {
-                    panic!("Instance {} not found in scope", name);
+                        .at(span)
+                    };
+                    return match (op, left) {
+                        (TokenType::And, Value::Bool(false)) => Ok(Value::Bool(false)),
+                        (TokenType::Or, Value::Bool(true)) => Ok(Value::Bool(true)),
+                        (_, Value::Bool(_)) => {
+                            match Interpreter::evaluate(right, scope, structScope)? {
+                                Value::Bool(b) => Ok(Value::Bool(b)),
+                                right => Err(notBoolean(right)),
+                            }
+                        }
+                        (_, left) => Err(notBoolean(left)),
+                    };
                 }
 
-                let structScopeMap = structScope.borrow_mut();
-                let instanceConstructor = structScopeMap
-                    .get(&name)
-                    .expect(format!("Struct {} not found in scope", name).as_str());
-
-                let mut fields: HashMap<String, Ast> = HashMap::new();
-                for (field, fieldValue) in members {
-                    if !instanceConstructor.contains_key(&field) {
-                        panic!("Field {} not found in struct {}", field, name);
-                    }
-                    fields.insert(
-                        field,
-                        Interpreter::evaluate(
-                            Box::new(fieldValue),
-                            scope.clone(),
-                            functionScope.clone(),
-                            structScope.clone(),
-                        ),
-                    );
+                let right = Interpreter::evaluate(right, scope, structScope)?;
+                Interpreter::binary(left, op, right).map_err(|error| error.at(span))
+            }
+            Ast::Literal(literal) => Ok(Value::from(literal.content.clone())),
+            Ast::Interpolation(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    let value = Interpreter::evaluate(part, scope.clone(), structScope.clone())?;
+                    result.push_str(&value.to_string());
                 }
-                return Ast::Instance(name, fields);
+                Ok(Value::String(result))
             }
-            Ast::Call(caller, mut args) => {
-                // println!("CALLER: {:?}", caller);
-                let caller = Interpreter::evaluate(
-                    caller,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
-
-                // println!("function caller: {:?}", caller);
-                let functionScopeCopy = functionScope.clone();
-
-                match caller {
-                    Ast::Func(name, callers, mut body) => {
-                        // println!("function name: {:?}", name);
-                        // println!("function args: {:?}", args);
-                        // println!("function body: {:?}", body);
-                        // println!(
-                        //     "{:?}",
-                        //     functionScopeCopy.borrow().keys().collect::<Vec<_>>()
-                        // );
-                        if !Interpreter::isFuncInScope(functionScopeCopy.clone(), name.clone()) {
-                            panic!("Function {} not found in scope", name);
-                        } else {
-                            let functionScopeMap = functionScope.borrow();
-                            let function = functionScopeMap
-                                .get(&name)
-                                .expect(format!("Function {} not found in scope", name).as_str());
-
-                            // this is unreal
-                            if name.starts_with("STDLIB_ARRAY") {
-                                // println!("args: {:?}", args);
-                                let array = body.get_mut(0).unwrap();
-                                // args.insert(0, array);
-                                /**
-                                 * TODO: figure out how to get this working for array fields of structs
-                                 */
-                                match name.as_str() {
-                                    "STDLIB_ARRAY_PUSH" => {
-                                        if args.len() != 1 {
-                                            panic!("Expected 1 argument, got {:?}", args.len());
-                                        }
-
-                                        // println!("STDLIB_ARRAY_PUSH args: {:?}", args);
-
-                                        let newElement = args.get(0).unwrap().clone();
-                                        if !matches!(array, Ast::Array(_)) {
-                                            panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            );
-                                        }
-                                        if !matches!(newElement, Ast::Literal(_)) {
-                                            panic!(
-                                                "Expected literal as second argument, got {:?}",
-                                                newElement
-                                            );
-                                        }
-                                        let mut array = match array {
-                                            Ast::Array(array) => array,
-                                            _ => panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            ),
-                                        };
-                                        let value = match newElement {
-                                            Ast::Literal(literal) => literal,
-                                            _ => panic!(
-                                                "Expected literal as second argument, got {:?}",
-                                                newElement
-                                            ),
-                                        };
-                                        array.content.push(Ast::Literal(value));
-
-                                        match &callers[..] {
-                                            [name] => {
-                                                scope.borrow_mut().insert(
-                                                    name.clone(),
-                                                    Ast::Array(array.clone()),
-                                                );
-                                            }
-                                            _ => {}
-                                        }
-
-                                        return Ast::Array(array.clone());
-                                    }
-                                    "STDLIB_ARRAY_POP" => {
-                                        if args.len() != 0 {
-                                            panic!("Expected 0 arguments, got {:?}", args.len());
-                                        }
-
-                                        // println!("STDLIB_ARRAY_POP args: {:?}", args);
-
-                                        if !matches!(array, Ast::Array(_)) {
-                                            panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            );
-                                        }
-                                        let mut actualArray: &mut Array = match array {
-                                            Ast::Array(_arr) => _arr,
-                                            _ => panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            ),
-                                        };
-                                        // array.content.pop();
-                                        actualArray.content.pop();
-
-                                        match &callers[..] {
-                                            [name] => {
-                                                scope.borrow_mut().insert(
-                                                    name.clone(),
-                                                    Ast::Array(actualArray.clone()),
-                                                );
-                                            }
-                                            _ => {}
-                                        }
-
-                                        return Ast::Array(actualArray.clone());
-                                    }
-                                    "STDLIB_ARRAY_REVERSE" => {
-                                        if args.len() != 0 {
-                                            panic!("Expected 0 arguments, got {:?}", args.len());
-                                        }
-
-                                        // println!("STDLIB_ARRAY_REVERSE args: {:?}", args);
-
-                                        if !matches!(array, Ast::Array(_)) {
-                                            panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            );
-                                        }
-                                        let mut actualArray: &mut Array = match array {
-                                            Ast::Array(_arr) => _arr,
-                                            _ => panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            ),
-                                        };
-                                        actualArray.content.reverse();
-
-                                        match &callers[..] {
-                                            [name] => {
-                                                scope.borrow_mut().insert(
-                                                    name.clone(),
-                                                    Ast::Array(actualArray.clone()),
-                                                );
-                                            }
-                                            _ => {}
-                                        }
-
-                                        return Ast::Array(actualArray.clone());
-                                    }
-                                    "STDLIB_ARRAY_SORT" => {
-                                        if args.len() != 0 {
-                                            panic!("Expected 0 arguments, got {:?}", args.len());
-                                        }
-
-                                        // println!("STDLIB_ARRAY_SORT args: {:?}", args);
-
-                                        if !matches!(array, Ast::Array(_)) {
-                                            panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            );
-                                        }
-                                        let mut actualArray: &mut Array = match array {
-                                            Ast::Array(_arr) => _arr,
-                                            _ => panic!(
-                                                "Expected array as first argument, got {:?}",
-                                                array
-                                            ),
-                                        };
-
-                                        let underLyingArray = actualArray.content.clone();
-                                        if underLyingArray.len() == 0 {
-                                            return Ast::Array(actualArray.clone());
-                                        }
-
-                                        // sort the array, but fits check they all have the same tokencontenttype
-                                        // convert from tokencontenttype to primitive
-                                        // then convert back
-                                        let mut sortedArray = underLyingArray.clone();
-                                        sortedArray.sort_by(|a, b| {
-                                            let ab = match a {
-                                                Ast::Literal(Literal {
-                                                    content: TokenContentType::Number(a),
-                                                }) => Some(a),
-                                                _ => None,
-                                            };
-                                            let bb = match b {
-                                                Ast::Literal(Literal {
-                                                    content: TokenContentType::Number(b),
-                                                }) => Some(b),
-                                                _ => None,
-                                            };
-                                            if ab.is_some() && bb.is_some() {
-                                                return ab
-                                                    .unwrap()
-                                                    .partial_cmp(&bb.unwrap())
-                                                    .unwrap();
-                                            }
-
-                                            let a = match a {
-                                                Ast::Literal(Literal {
-                                                    content: TokenContentType::String(abs),
-                                                }) => Some(abs),
-                                                _ => None,
-                                            };
-                                            let b = match b {
-                                                Ast::Literal(Literal {
-                                                    content: TokenContentType::String(b),
-                                                }) => Some(b),
-                                                _ => None,
-                                            };
-                                            if a.is_some() && b.is_some() {
-                                                return a.partial_cmp(&b).unwrap();
-                                            }
-                                            panic!("Expected uniform array of either all numbers, or all strings")
-                                        });
-
-                                        actualArray.content = sortedArray;
-
-                                        match &callers[..] {
-                                            [name] => {
-                                                scope.borrow_mut().insert(
-                                                    name.clone(),
-                                                    Ast::Array(actualArray.clone()),
-                                                );
-                                            }
-                                            _ => {}
-                                        }
-
-                                        return Ast::Array(actualArray.clone());
-                                    }
-                                    _ => {
-                                        panic!("Function {} not found in scope", name);
-                                    }
-                                }
-                            }
+            Ast::Array(array) => Ok(Value::array(Interpreter::elements(
+                &array.content,
+                scope,
+                structScope,
+            )?)),
+            Ast::Instance(name, members, span) => {
+                if !Interpreter::isStructInScope(structScope.clone(), name) {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Name,
+                        format!("Struct {} not found in scope", name),
+                    )
+                    .at(span));
+                }
 
-                            // println!("found function YEET {}", name);
-                            return function(args);
-                        }
-                    }
-                    _ => {
-                        panic!("Expected function but got {:?}", caller);
+                // cloned so the borrow is released before members (maybe instances too) are evaluated
+                let declared = structScope.borrow().get(name).cloned().unwrap();
+                for field in members.keys() {
+                    if !declared.contains(field) {
+                        return Err(RuntimeError::new(
+                            ErrorKind::Property,
+                            format!("Field {} not found in struct {}", field, name),
+                        )
+                        .at(span));
                     }
                 }
-            }
-            Ast::Get(caller_ast, property_ast, is_expr) => {
-                let orig_caller = caller_ast.clone();
-                // Evaluate the caller
-                let mut caller = Interpreter::evaluate(
-                    caller_ast,
-                    scope.clone(),
-                    functionScope.clone(),
-                    structScope.clone(),
-                );
 
-                // println!("get caller: {:?}", caller);
-
-                // Determine the property
-                let property = if is_expr {
-                    Interpreter::evaluate(
-                        property_ast,
-                        scope.clone(),
-                        functionScope.clone(),
-                        structScope.clone(),
-                    )
-                } else {
-                    *property_ast
-                };
-
-                match &mut caller {
-                    Ast::Array(array) => match property.clone() {
-                        Ast::Literal(Literal {
-                            content: TokenContentType::Number(_),
-                        }) => {
-                            if let Ast::Literal(Literal {
-                                content: TokenContentType::Number(n),
-                            }) = property
-                            {
-                                return array.content.get(n as usize).unwrap().clone();
-                            } else {
-                                panic!("Expected number as index, got {:?}", property);
-                            }
+                // members left out of `prep` start as None, so they can still be assigned later
+                let mut fields = vec![];
+                for field in declared {
+                    let value = match members.get(&field) {
+                        Some(member) => {
+                            Interpreter::evaluate(member, scope.clone(), structScope.clone())?
                         }
-                        Ast::Literal(Literal {
-                            content: TokenContentType::String(s),
-                        }) => match s.as_str() {
-                            // string standard library
-                            "length" => {
-                                return Ast::Literal(Literal {
-                                    content: TokenContentType::Number(array.content.len() as f64),
-                                });
-                            }
-                            "push" => {
-                                /*
-                                 * NOTE:
-                                 *
-                                 * essentially how this works is that the Ast::Func has three parts
-                                 * name, params, and body
-                                 *
-                                 * because we don't have a way to define functions on arrays, we are instead manually doing it by
-                                 * creating a standard library function that takes in an array and a value and pushes the value to the array
-                                 *
-                                 * we don't have a way to get this array to the function arguments, so we are instead returning the array itself
-                                 * as part of the "body" arguments to be called when the functions is ran (not the actual body)
-                                 *
-                                 * so when calling, the interpreter checks if its an array function, and if it is, it will insert the array into the arguments
-                                 *
-                                 */
-                                // println!("pushing to array");
-                                // println!(
-                                //     "origCaller, caller, property, isExpr: {:?}, {:?}, {:?}, {:?}",
-                                //     orig_caller, array, property, is_expr
-                                // );
-
-                                let variableVec = if matches!(*orig_caller, Ast::Var(_, _)) {
-                                    let name = match *orig_caller {
-                                        Ast::Var(name, _) => name,
-                                        _ => panic!("Expected variable but got {:?}", orig_caller),
-                                    };
-                                    vec![name]
-                                } else {
-                                    vec![]
-                                };
-                                return Ast::Func(
-                                    "STDLIB_ARRAY_PUSH".to_string(),
-                                    variableVec,
-                                    vec![caller],
-                                );
-                            }
-                            "pop" => {
-                                let variableVec = if matches!(*orig_caller, Ast::Var(_, _)) {
-                                    let name = match *orig_caller {
-                                        Ast::Var(name, _) => name,
-                                        _ => panic!("Expected variable but got {:?}", orig_caller),
-                                    };
-                                    vec![name]
-                                } else {
-                                    vec![]
-                                };
-                                return Ast::Func(
-                                    "STDLIB_ARRAY_POP".to_string(),
-                                    variableVec,
-                                    vec![caller],
-                                );
-                            }
-                            "reverse" => {
-                                let variableVec = if matches!(*orig_caller, Ast::Var(_, _)) {
-                                    let name = match *orig_caller {
-                                        Ast::Var(name, _) => name,
-                                        _ => panic!("Expected variable but got {:?}", orig_caller),
-                                    };
-                                    vec![name]
-                                } else {
-                                    vec![]
-                                };
-
-                                return Ast::Func(
-                                    "STDLIB_ARRAY_REVERSE".to_string(),
-                                    variableVec,
-                                    vec![caller],
-                                );
-                            }
-                            "sort" => {
-                                let variableVec = if matches!(*orig_caller, Ast::Var(_, _)) {
-                                    let name = match *orig_caller {
-                                        Ast::Var(name, _) => name,
-                                        _ => panic!("Expected variable but got {:?}", orig_caller),
-                                    };
-                                    vec![name]
-                                } else {
-                                    vec![]
-                                };
-
-                                return Ast::Func(
-                                    "STDLIB_ARRAY_SORT".to_string(),
-                                    variableVec,
-                                    vec![caller],
-                                );
-                            }
-                            _ => {
-                                panic!("Property {} not found in array", s);
-                            }
-                        },
-                        _ => panic!("Expected number as index, got {:?}", property),
-                    },
-                    Ast::Instance(name, members) => {
-                        let propertyKey = match property {
-                            Ast::Literal(Literal {
-                                content: TokenContentType::String(s),
-                            }) => s,
-                            _ => panic!("Expected string as property, got {:?}", property),
-                        };
-
-                        if !members.contains_key(&propertyKey.to_string()) {
-                            panic!("Property {} not found in instance {}", propertyKey, name);
-                        }
-                        return members.get(&propertyKey).unwrap().clone(); // WILL be a value or a function or SOMETHING
-                    }
-                    Ast::Var(name, _value) => {
-                        if !Interpreter::inScope(scope.clone(), name.clone()) {
-                            panic!("Variable {} not found in scope", name);
-                        }
-                        let value = scope.borrow_mut().get(name.as_str()).unwrap().clone();
-                        match value {
-                            Ast::Instance(_, _) => {
-                                let instance = value;
-                                let propertyKey = match property {
-                                    Ast::Literal(Literal {
-                                        content: TokenContentType::String(s),
-                                    }) => s,
-                                    _ => panic!("Expected string as property, got {:?}", property),
-                                };
-
-                                if !matches!(instance, Ast::Instance(_, _)) {
-                                    panic!("Expected instance but got {:?}", instance);
-                                }
-
-                                if let Ast::Instance(_, members) = instance {
-                                    if !members.contains_key(&propertyKey.to_string()) {
-                                        panic!(
-                                            "Property {} not found in instance {}",
-                                            propertyKey, name
-                                        );
-                                    }
-                                    return members.get(&propertyKey).unwrap().clone();
-                                // WILL be a value or a function or SOMETHING
-                                } else {
-                                    panic!("Expected instance but got {:?}", instance);
-                                }
-                            }
-                            _ => {
-                                panic!("Expected instance but got {:?}", value);
-                            }
-                        }
-                    }
-                    _ => {
-                        panic!("Expected instance but got {:?}", caller);
-                    }
+                        None => Value::None,
+                    };
+                    fields.push((field, value));
                 }
+                Ok(Value::instance(name, fields))
             }
-
-            _ => {
-                panic!("Expected expression but got statement {:?}", value);
+            Ast::Lambda(params, body) => Ok(Value::Function(Rc::new(Function {
+                name: "lambda".to_string(),
+                params: params.clone(),
+                body: body.clone(),
+                closure: scope,
+            }))),
+            Ast::Call(caller, args, named, span) => {
+                let caller = Interpreter::evaluate(caller, scope.clone(), structScope.clone())?;
+                // arguments are passed by value, evaluated in the caller's scope
+                let args = Interpreter::elements(args, scope.clone(), structScope.clone())?;
+                let mut namedArgs = vec![];
+                for (name, arg) in named {
+                    let value = Interpreter::evaluate(arg, scope.clone(), structScope.clone())?;
+                    namedArgs.push((name.clone(), value));
+                }
+                Interpreter::callWith(caller, args, namedArgs, structScope)
+                    .map_err(|error| error.calledAt(span))
+            }
+            Ast::Get(caller, property, isExpr, span) => {
+                let caller = Interpreter::evaluate(caller, scope.clone(), structScope.clone())?;
+                let key = Interpreter::key(property, *isExpr, scope, structScope)?;
+                Interpreter::property(caller, key).map_err(|error| error.at(span))
             }
+
+            _ => unreachable!("the parser only puts expressions here, got {:?}", value),
         }
     }
 
     pub fn execute(
-        node: Ast,
-        _scope: Scope,
-        _functionScope: FunctionScope,
-        _structScope: StructScope,
-    ) -> (Scope, Option<Ast>) {
-        // println!("executing {:?}", node);
-        let mut retValue = None;
-        let retScope = _scope.clone();
-        let retFunctionScope = _functionScope.clone();
-        let retStructScope = _structScope.clone();
+        node: &Ast,
+        scope: Scope,
+        structScope: StructScope,
+    ) -> Result<ControlFlow, RuntimeError> {
         match node {
-            Ast::Var(name, Some(value)) => {
-                let value = Interpreter::evaluate(
-                    value,
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                ); // pray to god scope.clone works here
-                retScope.borrow_mut().insert(name, value);
+            Ast::Var(name, Some(value), _) => {
+                let value = Interpreter::evaluate(value, scope.clone(), structScope)?;
+                scope.borrow_mut().declare(name.clone(), value);
             }
-            // TODO: lookup correct impl, see why it returns a function
-            Ast::Struct(id, params) => {
-                // let constructor = Box::new(move |fields: HashMap<String, Ast>| {
-                //     let mut instance = HashMap::new();
-                //     for (field, fieldValue) in fields {
-                //         if params.contains(&field) {
-                //             instance.insert(field, fieldValue);
-                //         } else {
-                //             panic!("Field {} not found in struct {}", field, id);
-                //         }
-                //     }
-                //     Ast::Instance(id.clone(), instance)
-                // });
-                let mut fields = HashMap::new();
-                for field in &params {
-                    fields.insert(field.to_owned(), Ast::None);
-                }
-
-                retStructScope.borrow_mut().insert(id, fields);
+            Ast::Struct(id, params, _) => {
+                structScope.borrow_mut().insert(id.clone(), params.clone());
             }
-            Ast::Func(name, params, body) => {
-                let functionScope = Rc::clone(&retFunctionScope);
-                let valueScope = Rc::clone(&retScope);
-                let structureScope = Rc::clone(&retStructScope);
-                let functionBody = body.clone();
-
-                let function = Box::new(move |args: Vec<Ast>| {
-                    // println!("function args: {:?}", args);
-                    // println!("ENTERING function");
-                    // let localScope = Rc::new(RefCell::new(HashMap::new()));
-                    let localScope = valueScope.clone();
-                    // for (key, value) in valueScope.borrow().iter() {
-                    //     localScope.borrow_mut().insert(key.clone(), value.clone());
-                    // }
-                    // println!("function params: {:?}", params);
-                    // println!("function args: {:?}", args);
-                    for (i, param) in params.iter().enumerate() {
-                        localScope
-                            .borrow_mut()
-                            .insert(param.clone(), args.get(i).unwrap().clone());
-                    }
-
-                    let functionScope = Rc::clone(&functionScope);
-
-                    // println!("localscope: {:?}", localScope);
-                    // println!("function body: {:?}", functionBody.clone());
-
-                    let result = Interpreter::run(
-                        functionBody.clone(),
-                        localScope,
-                        functionScope.clone(),
-                        structureScope.clone(),
-                    );
-
-                    // println!("function result: {:?}", result);
-
-                    // println!("EXITING function");
-
-                    match result.1 {
-                        Some(value) => value,
-                        None => Ast::None,
-                    }
-                });
-
-                retFunctionScope.borrow_mut().insert(name.clone(), function);
+            Ast::Func(name, params, body, _) => {
+                // the function's environment holds the function itself, so it can recurse
+                let function = Value::Function(Rc::new(Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: scope.clone(),
+                }));
+                scope.borrow_mut().declare(name.clone(), function);
             }
             Ast::Return(value) => {
-                let value = Interpreter::evaluate(
-                    value,
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                );
-                retValue = Some(value);
-                return (retScope, retValue);
+                let value = Interpreter::evaluate(value, scope, structScope)?;
+                return Ok(ControlFlow::Return(value));
             }
-            Ast::While(condition, body) => loop {
-                // // println!("STARTING WHILE LOOP!");
-                loop {
-                    // // println!("condition {:?}", condition);
-                    // // println!("calling execute from while loop");
-                    if !matches!(
-                        Interpreter::evaluate(
-                            condition.clone(),
-                            retScope.clone(),
-                            retFunctionScope.clone(),
-                            retStructScope.clone(),
-                        ),
-                        Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(true)
-                        })
-                    ) {
-                        // // println!("BREAKING WHILE LOOP!");
-                        break;
-                    }
-
-                    // // println!("{:?}", format!("condition {:?}", condition));
-                    let result = Interpreter::execute(
-                        *condition.clone(),
-                        retScope.clone(),
-                        retFunctionScope.clone(),
-                        retStructScope.clone(),
-                    );
-                    // // println!("result in while {:?}", result);
-                    let condition = result.1.unwrap();
-                    match condition {
-                        Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(false),
-                        }) => break,
-                        Ast::Literal(Literal {
-                            content: TokenContentType::Boolean(true),
-                        }) => {
-                            Interpreter::run(
-                                body.clone(),
-                                retScope.clone(),
-                                retFunctionScope.clone(),
-                                retStructScope.clone(),
-                            );
-                        }
-                        _ => {
-                            panic!(
-                                "Expected boolean value in while condition, got {:?}",
-                                condition
-                            );
+            Ast::Break(label) => {
+                return Ok(ControlFlow::Break(label.clone()));
+            }
+            Ast::Continue(label) => {
+                return Ok(ControlFlow::Continue(label.clone()));
+            }
+            Ast::While(condition, body, label, keyword) => loop {
+                let conditionEvaluated =
+                    Interpreter::evaluate(condition, scope.clone(), structScope.clone())?;
+                match conditionEvaluated {
+                    Value::Bool(false) => break,
+                    Value::Bool(true) => {
+                        let flow = Interpreter::run(
+                            body,
+                            Environment::child(&scope),
+                            structScope.clone(),
+                        )?;
+                        match flow {
+                            ControlFlow::Break(target) if ControlFlow::reaches(&target, label) => {
+                                break
+                            }
+                            ControlFlow::Continue(target)
+                                if ControlFlow::reaches(&target, label) => {}
+                            ControlFlow::Normal => {}
+                            flow => return Ok(flow),
                         }
                     }
+                    _ => {
+                        return Err(Interpreter::typeError(
+                            condition,
+                            keyword,
+                            format!(
+                                "Expected boolean value in while condition, got {}",
+                                conditionEvaluated.typeName()
+                            ),
+                        ));
+                    }
                 }
-                return (retScope, retValue);
             },
-            Ast::For(id, range, body) => {
+            Ast::For(id, range, body, label, keyword) => {
                 assert!(range.len() == 2);
-                let localScope = retScope.clone();
-
-                let mut rangeBegin = 0;
-                let mut rangeEnd = 0;
-
-                let rangeBeginAst = Interpreter::evaluate(
-                    Box::new(range[0].clone()),
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                );
-                if !matches!(
-                    rangeBeginAst,
-                    Ast::Literal(Literal {
-                        content: TokenContentType::Number(_)
-                    })
-                ) {
-                    panic!("Expected number as range begin, got {:?}", rangeBegin);
-                } else {
-                    if let Ast::Literal(Literal {
-                        content: TokenContentType::Number(n),
-                    }) = rangeBeginAst
-                    {
-                        rangeBegin = n as i64;
+                let mut bounds = vec![];
+                for bound in range {
+                    match Interpreter::evaluate(bound, scope.clone(), structScope.clone())? {
+                        Value::Number(n) => bounds.push(n as i64),
+                        value => {
+                            return Err(Interpreter::typeError(
+                                bound,
+                                keyword,
+                                format!("Expected number as range bound, got {}", value.typeName()),
+                            ))
+                        }
                     }
                 }
+                let (rangeBegin, rangeEnd) = (bounds[0], bounds[1]);
 
-                localScope.borrow_mut().insert(
-                    id.clone(),
-                    Ast::Literal(Literal {
-                        content: rangeBegin.into(),
-                    }),
-                );
-
-                let rangeEndAst = Interpreter::evaluate(
-                    Box::new(range[1].clone()),
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                );
-                if !matches!(
-                    rangeEndAst,
-                    Ast::Literal(Literal {
-                        content: TokenContentType::Number(_)
-                    })
-                ) {
-                    panic!("Expected number as range end, got {:?}", rangeEnd);
-                } else {
-                    if let Ast::Literal(Literal {
-                        content: TokenContentType::Number(n),
-                    }) = rangeEndAst
-                    {
-                        rangeEnd = n as i64;
-                    }
-                }
+                // the loop variable lives in its own environment around the body's
+                let localScope = Environment::child(&scope);
+                localScope
+                    .borrow_mut()
+                    .declare(id.clone(), Value::Number(rangeBegin as f64));
 
                 for _ in rangeBegin..rangeEnd {
-                    Interpreter::run(
-                        body.clone(),
-                        localScope.clone(),
-                        retFunctionScope.clone(),
-                        retStructScope.clone(),
-                    );
+                    let flow = Interpreter::run(
+                        body,
+                        Environment::child(&localScope),
+                        structScope.clone(),
+                    )?;
+                    match flow {
+                        ControlFlow::Break(target) if ControlFlow::reaches(&target, label) => break,
+                        // falls through to the increment like a normal iteration
+                        ControlFlow::Continue(target) if ControlFlow::reaches(&target, label) => {}
+                        ControlFlow::Normal => {}
+                        flow => return Ok(flow),
+                    }
 
                     // increment the loop variable
-                    let copyId = id.clone();
-                    let loopVar = localScope.borrow_mut().get(&copyId).unwrap().clone();
-                    if let Ast::Literal(Literal {
-                        content: TokenContentType::Number(n),
-                    }) = loopVar
-                    {
-                        localScope.borrow_mut().insert(
-                            id.clone(),
-                            Ast::Literal(Literal {
-                                content: (n as i64 + 1).into(),
-                            }),
-                        );
+                    let loopVar = localScope.borrow().get(id).unwrap();
+                    if let Value::Number(n) = loopVar {
+                        localScope
+                            .borrow_mut()
+                            .declare(id.clone(), Value::Number(n + 1.0));
                     }
                 }
             }
             Ast::Conditional(condition, ifBody, elseBody) => {
-                let conditionEvaluated = Interpreter::evaluate(
-                    condition,
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                );
-                if matches!(
-                    conditionEvaluated,
-                    Ast::Literal(Literal {
-                        content: TokenContentType::Boolean(false)
-                    })
-                ) {
+                let conditionEvaluated =
+                    Interpreter::evaluate(condition, scope.clone(), structScope.clone())?;
+                if matches!(conditionEvaluated, Value::Bool(false)) {
                     for statement in elseBody {
-                        // println!("calling execute from conditional");
-                        Interpreter::execute(
-                            statement,
-                            retScope.clone(),
-                            retFunctionScope.clone(),
-                            retStructScope.clone(),
-                        );
+                        let flow =
+                            Interpreter::execute(statement, scope.clone(), structScope.clone())?;
+                        if !matches!(flow, ControlFlow::Normal) {
+                            return Ok(flow);
+                        }
                     }
                 } else {
-                    Interpreter::run(
-                        ifBody,
-                        retScope.clone(),
-                        retFunctionScope.clone(),
-                        retStructScope.clone(),
-                    );
+                    return Interpreter::run(ifBody, Environment::child(&scope), structScope);
                 }
             }
-            Ast::Set(caller, _, value) => {
-                if !Interpreter::inScope(retScope.clone(), caller.clone()) {
-                    panic!("Instance {} not found in scope", caller);
+            Ast::Assign(target, value) => match target.as_ref() {
+                Ast::Var(name, _, span) => {
+                    let value = Interpreter::evaluate(value, scope.clone(), structScope)?;
+                    if !scope.borrow_mut().assign(name, value) {
+                        return Err(RuntimeError::new(
+                            ErrorKind::Name,
+                            format!("Variable {} not found in scope", name),
+                        )
+                        .at(span));
+                    }
                 }
+                Ast::Get(object, property, isExpr, span) => {
+                    let object = Interpreter::evaluate(object, scope.clone(), structScope.clone())?;
+                    let key =
+                        Interpreter::key(property, *isExpr, scope.clone(), structScope.clone())?;
+                    let value = Interpreter::evaluate(value, scope, structScope)?;
+                    Interpreter::setProperty(&object, key, value)
+                        .map_err(|error| error.at(span))?;
+                }
+                _ => unreachable!(
+                    "the parser only allows assigning to variables, fields and elements"
+                ),
+            },
+            Ast::CompoundAssign(target, op, value) => match target.as_ref() {
+                Ast::Var(name, _, span) => {
+                    let current = scope.borrow().get(name).ok_or_else(|| {
+                        RuntimeError::new(
+                            ErrorKind::Name,
+                            format!("Variable {} not found in scope", name),
+                        )
+                        .at(span)
+                    })?;
+                    let value = Interpreter::evaluate(value, scope.clone(), structScope)?;
+                    let updated =
+                        Interpreter::binary(current, op, value).map_err(|error| error.at(span))?;
+                    scope.borrow_mut().assign(name, updated);
+                }
+                Ast::Get(object, property, isExpr, span) => {
+                    // the object and key are worked out once, so `a[next()] += 1` only calls `next` once
+                    let object = Interpreter::evaluate(object, scope.clone(), structScope.clone())?;
+                    let key =
+                        Interpreter::key(property, *isExpr, scope.clone(), structScope.clone())?;
+                    let current = Interpreter::property(object.clone(), key.clone())
+                        .map_err(|error| error.at(span))?;
+                    let value = Interpreter::evaluate(value, scope, structScope)?;
+                    Interpreter::binary(current, op, value)
+                        .and_then(|updated| Interpreter::setProperty(&object, key, updated))
+                        .map_err(|error| error.at(span))?;
+                }
+                _ => unreachable!(
+                    "the parser only allows assigning to variables, fields and elements"
+                ),
+            },
 
-                let instance = retScope.borrow_mut().get(&caller).unwrap().clone();
-                if let Ast::Instance(name, mut members) = instance {
-                    let value = Interpreter::evaluate(
-                        value,
-                        retScope.clone(),
-                        retFunctionScope.clone(),
-                        retStructScope.clone(),
-                    );
-
-                    let propertyKey = match value.clone() {
-                        Ast::Literal(Literal {
-                            content: TokenContentType::String(s),
-                        }) => s,
-                        _ => panic!("Expected string as property, got {:?}", value),
-                    };
+            _ => {
+                // an expression statement, run for its side effects
+                Interpreter::evaluate(node, scope, structScope)?;
+            }
+        }
+        Ok(ControlFlow::Normal)
+    }
 
-                    if !members.contains_key(&propertyKey.to_string()) {
-                        panic!("Property {} not found in instance {}", propertyKey, name);
-                    }
-                    members.insert(propertyKey, value);
-                    retScope
-                        .borrow_mut()
-                        .insert(name.clone(), Ast::Instance(name, members));
-                    return (retScope, None);
-                } else {
-                    panic!("Expected instance but got {:?}", instance);
+    fn binary(left: Value, op: &TokenType, right: Value) -> Result<Value, RuntimeError> {
+        match op {
+            TokenType::Plus => left + right,
+            TokenType::Minus => left - right,
+            TokenType::Asterisk => left * right,
+            TokenType::Slash => left / right,
+            TokenType::Modulo => left % right,
+            TokenType::Power => left.pow(right),
+            TokenType::FloorDiv => left.floorDiv(right),
+            TokenType::BitAnd => left & right,
+            TokenType::BitOr => left | right,
+            TokenType::BitXor => left ^ right,
+            TokenType::ShiftLeft => left << right,
+            TokenType::ShiftRight => left >> right,
+            TokenType::Equiv => Ok(Value::Bool(left == right)),
+            TokenType::NotEquiv => Ok(Value::Bool(left != right)),
+            TokenType::Gt => Ok(Value::Bool(
+                left.order(&right, ">")?.is_some_and(Ordering::is_gt),
+            )),
+            TokenType::Gte => Ok(Value::Bool(
+                left.order(&right, ">=")?.is_some_and(Ordering::is_ge),
+            )),
+            TokenType::Lt => Ok(Value::Bool(
+                left.order(&right, "<")?.is_some_and(Ordering::is_lt),
+            )),
+            TokenType::Lte => Ok(Value::Bool(
+                left.order(&right, "<=")?.is_some_and(Ordering::is_le),
+            )),
+            _ => unreachable!("{:?} is not a binary operator", op),
+        }
+    }
+
+    /**
+     * Calls a function value with arguments that are already evaluated. Also used by builtins
+     * that take a function to call back.
+     */
+    pub fn call(
+        callee: Value,
+        args: Vec<Value>,
+        structScope: StructScope,
+    ) -> Result<Value, RuntimeError> {
+        Interpreter::callWith(callee, args, vec![], structScope)
+    }
+
+    // how many nested script function calls are allowed before it's a stack overflow
+    pub fn setMaxDepth(depth: usize) {
+        MAX_DEPTH.set(depth);
+    }
+
+    /**
+     * Sets how much stack evaluation may use on this thread, counting from the caller's frame,
+     * so it should be called at the top of the thread with the size the thread was made with.
+     */
+    pub fn setStackSize(size: usize) {
+        let limit = Interpreter::stackAddress()
+            .saturating_sub(size)
+            .saturating_add(STACK_RESERVE);
+        STACK_LIMIT.set(limit);
+    }
+
+    // roughly where the stack has got to; it grows down, so this shrinks as calls nest
+    fn stackAddress() -> usize {
+        let marker = 0u8;
+        std::ptr::addr_of!(marker) as usize
+    }
+
+    /**
+     * Whatever the depth limit, a long enough expression in each call uses up the stack first,
+     * so evaluation also stops with a stack overflow once the stack is nearly gone.
+     */
+    fn checkStack() -> Result<(), RuntimeError> {
+        if Interpreter::stackAddress() < STACK_LIMIT.get() {
+            return Err(RuntimeError::new(
+                ErrorKind::StackOverflow,
+                format!("Ran out of stack space at call depth {}", DEPTH.get()),
+            ));
+        }
+        Ok(())
+    }
+
+    // runs a function body one call deeper, or refuses with a stack overflow at the maximum depth
+    fn nested<T>(body: impl FnOnce() -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+        Interpreter::checkStack()?;
+        let depth = DEPTH.get();
+        if depth >= MAX_DEPTH.get() {
+            return Err(RuntimeError::new(
+                ErrorKind::StackOverflow,
+                format!("Maximum call depth of {} exceeded", MAX_DEPTH.get()),
+            ));
+        }
+        DEPTH.set(depth + 1);
+        let result = body();
+        DEPTH.set(depth);
+        result
+    }
+
+    // `call`, with `name: value` arguments as well
+    fn callWith(
+        callee: Value,
+        args: Vec<Value>,
+        named: Vec<(String, Value)>,
+        structScope: StructScope,
+    ) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Function(function) => {
+                // defaults are evaluated while binding, so they count as part of the call too
+                let flow = Interpreter::nested(|| {
+                    let localScope =
+                        Interpreter::bind(&function, args, named, structScope.clone())?;
+                    Interpreter::run(&function.body, localScope, structScope)
+                        .map_err(|error| error.inFunction(&function.name))
+                })?;
+                match flow {
+                    ControlFlow::Return(value) => Ok(value),
+                    _ => Ok(Value::None),
                 }
             }
+            Value::NativeFunction(function) => {
+                if let Some((name, _)) = named.first() {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Argument,
+                        format!(
+                            "{} doesn't take named arguments, got {}",
+                            function.name, name
+                        ),
+                    ));
+                }
+                (function.function)(args)
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Expected function but got {}", callee.typeName()),
+            )),
+        }
+    }
 
-            _ => {
-                // println!("running node {:?}", node);
-                let result = Interpreter::evaluate(
-                    Box::new(node.clone()),
-                    retScope.clone(),
-                    retFunctionScope.clone(),
-                    retStructScope.clone(),
-                );
-                // println!("result: {:?}", result);
-                return (retScope.clone(), Some(result));
+    /**
+     * Makes the frame for a call: a fresh environment inside the one the function was defined
+     * in, with every parameter declared. Arguments fill parameters by position, then by name,
+     * and whatever is left takes its default.
+     */
+    fn bind(
+        function: &Function,
+        args: Vec<Value>,
+        named: Vec<(String, Value)>,
+        structScope: StructScope,
+    ) -> Result<Scope, RuntimeError> {
+        // the rest parameter, if any, takes whatever positional arguments are left over
+        let (params, rest) = match function.params.split_last() {
+            Some((last, params)) if last.rest => (params, Some(last)),
+            _ => (&function.params[..], None),
+        };
+        let mut args = args;
+        let extra = if rest.is_some() && args.len() > params.len() {
+            args.split_off(params.len())
+        } else {
+            vec![]
+        };
+        if args.len() > params.len() {
+            let required = params
+                .iter()
+                .filter(|param| param.default.is_none())
+                .count();
+            let takes = if required == params.len() {
+                "takes"
+            } else {
+                "takes at most"
+            };
+            return Err(RuntimeError::new(
+                ErrorKind::Argument,
+                format!(
+                    "{} {} {} argument(s) but got {}",
+                    function.name,
+                    takes,
+                    params.len(),
+                    args.len()
+                ),
+            ));
+        }
+
+        let mut values: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+        values.resize(params.len(), None);
+        for (name, value) in named {
+            let Some(i) = params.iter().position(|param| param.name == name) else {
+                return Err(RuntimeError::new(
+                    ErrorKind::Argument,
+                    format!("{} has no parameter named {}", function.name, name),
+                ));
+            };
+            if values[i].is_some() {
+                return Err(RuntimeError::new(
+                    ErrorKind::Argument,
+                    format!("{} got more than one value for {}", function.name, name),
+                ));
+            }
+            values[i] = Some(value);
+        }
+
+        let localScope = Environment::child(&function.closure);
+        for (param, value) in params.iter().zip(values) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                // evaluated in the new frame, so a default can use the parameters before it
+                (None, Some(default)) => {
+                    Interpreter::evaluate(default, localScope.clone(), structScope.clone())
+                        .map_err(|error| error.inFunction(&function.name))?
+                }
+                (None, None) => {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Argument,
+                        format!("Missing argument {} for {}", param.name, function.name),
+                    ))
+                }
+            };
+            localScope.borrow_mut().declare(param.name.clone(), value);
+        }
+        if let Some(rest) = rest {
+            localScope
+                .borrow_mut()
+                .declare(rest.name.clone(), Value::array(extra));
+        }
+        Ok(localScope)
+    }
+
+    // runs an array method on `items`, returning the array it changed
+    fn arrayMethod(
+        items: &Rc<RefCell<Vec<Value>>>,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let expected = if matches!(method, "push" | "add") {
+            1
+        } else {
+            0
+        };
+        if args.len() != expected {
+            return Err(RuntimeError::new(
+                ErrorKind::Argument,
+                format!(
+                    "Expected {} argument(s) for {}, got {}",
+                    expected,
+                    method,
+                    args.len()
+                ),
+            ));
+        }
+
+        let mut array = items.borrow_mut();
+        match method {
+            "push" | "add" => array.extend(args),
+            "pop" => {
+                array.pop();
+            }
+            "reverse" => array.reverse(),
+            "sort" => {
+                let uniform = array.iter().all(|item| matches!(item, Value::Number(_)))
+                    || array.iter().all(|item| matches!(item, Value::String(_)));
+                if !uniform {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Type,
+                        "Expected uniform array of either all numbers, or all strings",
+                    ));
+                }
+                array.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            }
+            _ => unreachable!("{} is not an array method", method),
+        }
+        Ok(Value::Array(Rc::clone(items)))
+    }
+
+    // the key a `.name` or `[expr]` access looks up
+    fn key(
+        property: &Ast,
+        isExpr: bool,
+        scope: Scope,
+        structScope: StructScope,
+    ) -> Result<Value, RuntimeError> {
+        if isExpr {
+            return Interpreter::evaluate(property, scope, structScope);
+        }
+        match property {
+            Ast::Literal(Literal {
+                content: TokenContentType::String(name),
+            }) => Ok(Value::String(name.clone())),
+            _ => unreachable!("the parser only builds `.name` accesses with a name"),
+        }
+    }
+
+    fn property(caller: Value, key: Value) -> Result<Value, RuntimeError> {
+        match (caller, key) {
+            (Value::Array(items), Value::Number(n)) => {
+                let items = items.borrow();
+                Ok(items[Interpreter::index(n, items.len())?].clone())
+            }
+            (Value::Array(items), Value::String(name)) => match name.as_str() {
+                "length" => Ok(Value::Number(items.borrow().len() as f64)),
+                // bound to this array, so `let add = arr.push` still pushes onto `arr`
+                method if ARRAY_METHODS.contains(&method) => {
+                    let method = method.to_string();
+                    Ok(Value::native(&name, move |args| {
+                        Interpreter::arrayMethod(&items, &method, args)
+                    }))
+                }
+                _ => Err(RuntimeError::new(
+                    ErrorKind::Property,
+                    format!("Property {} not found in array", name),
+                )),
+            },
+            (Value::Instance(instance), Value::String(name)) => {
+                let instance = instance.borrow();
+                instance.get(&name).cloned().ok_or_else(|| {
+                    RuntimeError::new(
+                        ErrorKind::Property,
+                        format!("Property {} not found in instance {}", name, instance.name),
+                    )
+                })
+            }
+            (caller, key) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Cannot get {} of {}", key, caller.typeName()),
+            )),
+        }
+    }
+
+    // writes an element or field in place, for every reference to `object` to see
+    fn setProperty(object: &Value, key: Value, value: Value) -> Result<(), RuntimeError> {
+        match (object, key) {
+            (Value::Array(items), Value::Number(n)) => {
+                let mut items = items.borrow_mut();
+                let index = Interpreter::index(n, items.len())?;
+                items[index] = value;
+            }
+            (Value::Instance(instance), Value::String(property)) => {
+                let mut instance = instance.borrow_mut();
+                let name = instance.name.clone();
+                *instance.get_mut(&property).ok_or_else(|| {
+                    RuntimeError::new(
+                        ErrorKind::Property,
+                        format!("Property {} not found in instance {}", property, name),
+                    )
+                })? = value;
+            }
+            (object, key) => {
+                return Err(RuntimeError::new(
+                    ErrorKind::Type,
+                    format!("Cannot assign to {} of {}", key, object.typeName()),
+                ))
             }
         }
-        (retScope, retValue)
+        Ok(())
+    }
+
+    fn index(n: f64, length: usize) -> Result<usize, RuntimeError> {
+        if n < 0.0 || n.fract() != 0.0 || n as usize >= length {
+            return Err(RuntimeError::new(
+                ErrorKind::Index,
+                format!("Index {} out of bounds for array of length {}", n, length),
+            ));
+        }
+        Ok(n as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // runs `program` with the copying builtins in scope, handing back its global variables
+    fn run(program: &str) -> Scope {
+        let tokens = Lexer::new(program.to_string(), "test.txt")
+            .scan_tokens()
+            .unwrap();
+        let (ast, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let scope = Environment::global();
+        scope.borrow_mut().declare(
+            "copy".to_string(),
+            Value::native("copy", |args| Ok(args[0].copy())),
+        );
+        scope.borrow_mut().declare(
+            "deepCopy".to_string(),
+            Value::native("deepCopy", |args| Ok(args[0].deepCopy())),
+        );
+        Interpreter::run(&ast, scope.clone(), Rc::new(RefCell::new(HashMap::new()))).unwrap();
+        scope
+    }
+
+    // the error `program` stops with
+    fn failure(program: &str) -> RuntimeError {
+        let tokens = Lexer::new(program.to_string(), "test.txt")
+            .scan_tokens()
+            .unwrap();
+        let (ast, _) = Parser::new(tokens).parse();
+        let structScope = Rc::new(RefCell::new(HashMap::new()));
+        Interpreter::run(&ast, Environment::global(), structScope).unwrap_err()
+    }
+
+    fn printed(scope: &Scope, name: &str) -> String {
+        scope.borrow().get(name).unwrap().to_string()
+    }
+
+    #[test]
+    fn assignmentShares() {
+        let scope = run("let a = [1, 2]\nlet b = a\nb.push(3)\n");
+        assert_eq!(printed(&scope, "a"), "[1, 2, 3]");
+        assert_eq!(printed(&scope, "b"), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn fieldOfAnElementChangesTheElement() {
+        let scope = run(
+            "struct Cell has { live }\nlet cells = [prep Cell(live: true), prep Cell(live: true)]\nlet i = 1\ncells[i].live = false\nlet first = cells[0]\nfirst.live = false\n",
+        );
+        assert_eq!(
+            printed(&scope, "cells"),
+            "[Cell {live: false}, Cell {live: false}]"
+        );
+    }
+
+    #[test]
+    fn compoundAssignmentUpdatesItsTargetInPlace() {
+        let scope = run(
+            "let a = [10, 20]\nlet calls = 0\nfunc next {\n    calls += 1\n    finished 1\n}\na[next()] += 5\nstruct P has { x }\nlet p = prep P(x: 2)\np.x *= 3\nlet n = 7\nn -= 2\nlet n %= 3\nlet s = \"a\"\ns += \"b\"\n",
+        );
+        // the target's path is only worked out once
+        assert_eq!(printed(&scope, "calls"), "1");
+        assert_eq!(printed(&scope, "a"), "[10, 25]");
+        assert_eq!(printed(&scope, "p"), "P {x: 6}");
+        assert_eq!(printed(&scope, "n"), "2");
+        assert_eq!(printed(&scope, "s"), "ab");
+
+        let error = failure("let x = 1\ny += 1\n");
+        assert!(matches!(error.kind, ErrorKind::Name));
+        assert_eq!(
+            error.to_string(),
+            "test.txt:2:1: name error: Variable y not found in scope"
+        );
+    }
+
+    #[test]
+    fn labeledJumpsLeaveTheLoopTheyName() {
+        let scope = run(
+            "let pairs = []\nouter: loop i through (0, 3) {\n    let j = 0\n    while (j < 3) {\n        j += 1\n        if (j == 2) {\n            continue outer\n        }\n        if (i == 2) {\n            break outer\n        }\n        pairs.push([i, j])\n    }\n}\nlet hits = 0\nloop i through (0, 4) {\n    loop j through (0, 4) {\n        if (j == 1) {\n            break\n        }\n        hits += 1\n    }\n    if (i == 1) {\n        continue\n    }\n    hits += 10\n}\n",
+        );
+        assert_eq!(printed(&scope, "pairs"), "[[0, 1], [1, 1]]");
+        // without a label they only leave the innermost loop
+        assert_eq!(printed(&scope, "hits"), "34");
+    }
+
+    #[test]
+    fn blocksAndFunctionsHaveTheirOwnScope() {
+        let scope = run(
+            "let x = 1\nif (true) {\n    let x = 2\n    let inside = 3\n}\nfunc counter {\n    let n = 0\n    finished func () {\n        n += 1\n        finished n\n    }\n}\nlet count = counter()\ncount()\ncount()\nlet third = count()\nlet fresh = counter()()\nfunc fact needs (n) {\n    if (n <= 1) {\n        finished 1\n    }\n    finished fact(n - 1) * n\n}\nlet f = fact(5)\nlet total = 0\nfunc add needs (v) {\n    total = total + v\n}\nadd(2)\nadd(3)\n",
+        );
+        assert_eq!(printed(&scope, "x"), "1");
+        assert!(scope.borrow().get("inside").is_none());
+        // each closure keeps its own `n` between calls
+        assert_eq!(printed(&scope, "third"), "3");
+        assert_eq!(printed(&scope, "fresh"), "1");
+        // `n` is still this call's own after the recursive call returns
+        assert_eq!(printed(&scope, "f"), "120");
+        assert_eq!(printed(&scope, "total"), "5");
+
+        let error = failure("func f {\n    z = 1\n}\nf()\n");
+        assert!(matches!(error.kind, ErrorKind::Name));
+        assert_eq!(error.message, "Variable z not found in scope");
+    }
+
+    #[test]
+    fn copyIsShallowAndDeepCopyIsNot() {
+        let scope = run(
+            "let nested = [[1], [2]]\nlet shallow = copy(nested)\nlet deep = deepCopy(nested)\nshallow.push([3])\nnested[0].push(9)\n",
+        );
+        assert_eq!(printed(&scope, "nested"), "[[1, 9], [2]]");
+        assert_eq!(printed(&scope, "shallow"), "[[1, 9], [2], [3]]");
+        assert_eq!(printed(&scope, "deep"), "[[1], [2]]");
+    }
+
+    #[test]
+    fn functionsChangeTheArraysTheyArePassed() {
+        let scope = run(
+            "func fill needs (xs) {\n    xs.push(0)\n    xs = [9]\n}\nlet a = []\nfill(a)\nfill(a)\n",
+        );
+        // changes to the array show through, but assigning the parameter only rebinds it
+        assert_eq!(printed(&scope, "a"), "[0, 0]");
+    }
+
+    #[test]
+    fn argumentsAreBoundToParameters() {
+        let scope = run(
+            "func area needs (w, h = w, unit = \"m\") {\n    finished \"{w * h}{unit}\"\n}\nlet square = area(3)\nlet named = area(2, unit: \"cm\", h: 5)\nlet allNamed = area(h: 1, w: 4)\n",
+        );
+        // a default can use the parameters before it
+        assert_eq!(printed(&scope, "square"), "9m");
+        assert_eq!(printed(&scope, "named"), "10cm");
+        assert_eq!(printed(&scope, "allNamed"), "4m");
+
+        let function = "func area needs (w, h = w) {\n    finished w * h\n}\n";
+        for (call, message) in [
+            (
+                "area(1, 2, 3)",
+                "area takes at most 2 argument(s) but got 3",
+            ),
+            ("area()", "Missing argument w for area"),
+            ("area(1, depth: 2)", "area has no parameter named depth"),
+            ("area(1, w: 2)", "area got more than one value for w"),
+        ] {
+            let error = failure(&format!("{}{}\n", function, call));
+            assert!(matches!(error.kind, ErrorKind::Argument), "{}", call);
+            assert_eq!(error.message, message);
+        }
+    }
+
+    #[test]
+    fn restParametersAndSpreading() {
+        let scope = run(
+            "func count needs (first, ...rest) {\n    finished rest.length\n}\nlet none = count(1)\nlet some = count(1, 2, 3)\nlet numbers = [4, 5]\nlet spread = count(...numbers, 6)\nlet joined = [0, ...numbers, ...[]]\n",
+        );
+        assert_eq!(printed(&scope, "none"), "0");
+        assert_eq!(printed(&scope, "some"), "2");
+        assert_eq!(printed(&scope, "spread"), "2");
+        assert_eq!(printed(&scope, "joined"), "[0, 4, 5]");
+
+        let error = failure("let n = 1\nlet a = [...n]\n");
+        assert!(matches!(error.kind, ErrorKind::Type));
+        assert_eq!(
+            error.to_string(),
+            "test.txt:2:10: type error: Can only spread an array, not number"
+        );
+    }
+
+    #[test]
+    fn comparingDifferentTypesIsATypeError() {
+        let error = failure("let x = 1 < \"a\"\n");
+        assert!(matches!(error.kind, ErrorKind::Type));
+        assert_eq!(
+            error.to_string(),
+            "test.txt:1:11: type error: Cannot compare number and string using <"
+        );
+
+        let scope = run("let x = 1 < 2\nlet y = \"b\" >= \"a\"\nlet z = [1, 2] > [1]\n");
+        assert_eq!(printed(&scope, "x"), "true");
+        assert_eq!(printed(&scope, "y"), "true");
+        assert_eq!(printed(&scope, "z"), "true");
+    }
+
+    #[test]
+    fn loopTypeErrorsHaveALocation() {
+        // literals have no span of their own, so these point at the loop's keyword
+        let error = failure("let x = 1\nwhile (1) {}\n");
+        assert_eq!(
+            error.to_string(),
+            "test.txt:2:1: type error: Expected boolean value in while condition, got number"
+        );
+        let error = failure("loop i through (0, \"a\") {}\n");
+        assert_eq!(
+            error.to_string(),
+            "test.txt:1:1: type error: Expected number as range bound, got string"
+        );
+    }
+
+    #[test]
+    fn defaultsCountTowardsTheDepth() {
+        Interpreter::setMaxDepth(20);
+        let error = failure("func f needs (x = f()) {\n    finished 1\n}\nf()\n");
+        assert!(matches!(error.kind, ErrorKind::StackOverflow));
+        assert_eq!(error.message, "Maximum call depth of 20 exceeded");
+    }
+
+    #[test]
+    fn runningOutOfStackIsAnError() {
+        // half a megabyte to use up, well inside the test thread's own stack
+        Interpreter::setMaxDepth(usize::MAX);
+        Interpreter::setStackSize(STACK_RESERVE + 512 * 1024);
+        let error = failure("func down needs (n) {\n    finished 1 + down(n + 1)\n}\ndown(0)\n");
+        assert!(matches!(error.kind, ErrorKind::StackOverflow));
+        assert!(error.message.starts_with("Ran out of stack space"));
     }
 }
@@ -1,13 +1,10 @@
-use std::collections::HashSet;
+use std::rc::Rc;
 
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
 
-fn KEYWORDS() -> HashSet<&'static str> {
-    HashSet::from([
-        "let", "=", "struct", "prep", "has", "func", "needs", "finished", "loop", "through",
-        "while", "if", "elif", "else",
-    ])
-}
+use crate::dialect::Dialect;
 
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
 pub enum TokenType {
@@ -18,6 +15,7 @@ pub enum TokenType {
     LeftBracket,
     RightBracket,
     Period,
+    Ellipsis,
     Comma,
     Colon,
     Keyword,
@@ -38,8 +36,21 @@ pub enum TokenType {
     Asterisk,
     Slash,
     Modulo,
+    Power,
+    FloorDiv,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    ModuloAssign,
     EOF,
     Boolean,
+    Interpolation,
 }
 
 impl Serialize for TokenType {
@@ -55,6 +66,7 @@ impl Serialize for TokenType {
             TokenType::LeftBracket => serializer.serialize_str("["),
             TokenType::RightBracket => serializer.serialize_str("]"),
             TokenType::Period => serializer.serialize_str("."),
+            TokenType::Ellipsis => serializer.serialize_str("..."),
             TokenType::Comma => serializer.serialize_str(","),
             TokenType::Colon => serializer.serialize_str(":"),
             TokenType::Keyword => serializer.serialize_str("Keyword"),
@@ -75,8 +87,21 @@ impl Serialize for TokenType {
             TokenType::Modulo => serializer.serialize_str("%"),
             TokenType::Asterisk => serializer.serialize_str("*"),
             TokenType::Slash => serializer.serialize_str("/"),
+            TokenType::Power => serializer.serialize_str("**"),
+            TokenType::FloorDiv => serializer.serialize_str("//"),
+            TokenType::BitAnd => serializer.serialize_str("&"),
+            TokenType::BitOr => serializer.serialize_str("|"),
+            TokenType::BitXor => serializer.serialize_str("^"),
+            TokenType::ShiftLeft => serializer.serialize_str("<<"),
+            TokenType::ShiftRight => serializer.serialize_str(">>"),
+            TokenType::PlusAssign => serializer.serialize_str("+="),
+            TokenType::MinusAssign => serializer.serialize_str("-="),
+            TokenType::AsteriskAssign => serializer.serialize_str("*="),
+            TokenType::SlashAssign => serializer.serialize_str("/="),
+            TokenType::ModuloAssign => serializer.serialize_str("%="),
             TokenType::EOF => serializer.serialize_str("EOF"),
             TokenType::Boolean => serializer.serialize_str("Boolean"),
+            TokenType::Interpolation => serializer.serialize_str("Interpolation"),
         }
     }
 }
@@ -91,8 +116,8 @@ impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Token {{ type: {:?},  value: {},  content: {:?}, line: {}, column: {} }}",
-            self._type, self.value, self.content, self.line, self.column
+            "Token {{ type: {:?},  value: {},  content: {:?}, span: {} }}",
+            self._type, self.value, self.content, self.span
         )
     }
 }
@@ -102,12 +127,21 @@ impl Serialize for Token {
     where
         S: serde::ser::Serializer,
     {
-        let mut state = serializer.serialize_struct("Token", 5)?;
+        let mut state = serializer.serialize_struct("Token", 6)?;
         state.serialize_field("type", &self._type)?;
         state.serialize_field("value", &self.value)?;
         state.serialize_field("content", &self.content)?;
-        state.serialize_field("line", &self.line)?;
-        state.serialize_field("column", &self.column)?;
+        state.serialize_field("span", &self.span)?;
+        if self.trivia.is_empty() {
+            state.skip_field("trivia")?;
+        } else {
+            state.serialize_field("trivia", &self.trivia)?;
+        }
+        if self.doc.is_none() {
+            state.skip_field("doc")?;
+        } else {
+            state.serialize_field("doc", &self.doc)?;
+        }
         state.end()
     }
 }
@@ -129,25 +163,73 @@ impl TokenType {
                 | TokenType::Asterisk
                 | TokenType::Slash
                 | TokenType::Modulo
+                | TokenType::Power
+                | TokenType::FloorDiv
+                | TokenType::BitAnd
+                | TokenType::BitOr
+                | TokenType::BitXor
+                | TokenType::ShiftLeft
+                | TokenType::ShiftRight
         )
     }
 
-    // order of operations
+    // how the token type reads in an error message
+    pub fn describe(&self) -> &'static str {
+        match self {
+            TokenType::LeftParen => "`(`",
+            TokenType::RightParen => "`)`",
+            TokenType::LeftBrace => "`{`",
+            TokenType::RightBrace => "`}`",
+            TokenType::LeftBracket => "`[`",
+            TokenType::RightBracket => "`]`",
+            TokenType::Period => "`.`",
+            TokenType::Ellipsis => "`...`",
+            TokenType::Comma => "`,`",
+            TokenType::Colon => "`:`",
+            TokenType::Keyword => "keyword",
+            TokenType::Identifier => "identifier",
+            TokenType::String | TokenType::Interpolation => "string",
+            TokenType::Number => "number",
+            TokenType::Boolean => "boolean",
+            TokenType::EOF => "end of file",
+            _ => "operator",
+        }
+    }
+
+    // the binary operator a compound assignment like `+=` applies
+    pub fn compoundOperator(&self) -> Option<TokenType> {
+        match self {
+            TokenType::PlusAssign => Some(TokenType::Plus),
+            TokenType::MinusAssign => Some(TokenType::Minus),
+            TokenType::AsteriskAssign => Some(TokenType::Asterisk),
+            TokenType::SlashAssign => Some(TokenType::Slash),
+            TokenType::ModuloAssign => Some(TokenType::Modulo),
+            _ => None,
+        }
+    }
+
+    // order of operations, loosest first
     pub fn precedence(&self) -> i32 {
         match self {
-            TokenType::Lt
-            | TokenType::Lte
-            | TokenType::Gt
-            | TokenType::Gte
-            | TokenType::Equiv
-            | TokenType::NotEquiv
-            | TokenType::And
-            | TokenType::Or => 0,
-            TokenType::Plus | TokenType::Minus => 1,
-            TokenType::Asterisk | TokenType::Slash | TokenType::Modulo => 2,
+            TokenType::Or => 1,
+            TokenType::And => 2,
+            TokenType::Equiv | TokenType::NotEquiv => 3,
+            TokenType::Lt | TokenType::Lte | TokenType::Gt | TokenType::Gte => 4,
+            TokenType::BitOr => 5,
+            TokenType::BitXor => 6,
+            TokenType::BitAnd => 7,
+            TokenType::ShiftLeft | TokenType::ShiftRight => 8,
+            TokenType::Plus | TokenType::Minus => 9,
+            TokenType::Asterisk | TokenType::Slash | TokenType::Modulo | TokenType::FloorDiv => 10,
+            TokenType::Power => 11,
             _ => -1,
         }
     }
+
+    // `2 ** 3 ** 2` is `2 ** (3 ** 2)`, everything else groups left to right
+    pub fn isRightAssociative(&self) -> bool {
+        matches!(self, TokenType::Power)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -155,6 +237,18 @@ pub enum TokenContentType {
     String(String),
     Number(f64),
     Boolean(bool),
+    Interpolation(Vec<StringPart>),
+}
+
+/**
+ * A piece of an interpolated string: either literal text or the tokens of an embedded
+ * expression, terminated by their own EOF token so they can be parsed on their own.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StringPart {
+    Text(String),
+    Code(Vec<Token>),
 }
 
 impl Serialize for TokenContentType {
@@ -166,112 +260,250 @@ impl Serialize for TokenContentType {
             TokenContentType::String(s) => serializer.serialize_str(s),
             TokenContentType::Number(n) => serializer.serialize_f64(*n),
             TokenContentType::Boolean(b) => serializer.serialize_bool(*b),
+            TokenContentType::Interpolation(parts) => parts.serialize(serializer),
         }
     }
 }
 
-impl Into<TokenContentType> for String {
-    fn into(self) -> TokenContentType {
-        TokenContentType::String(self)
+impl From<String> for TokenContentType {
+    fn from(val: String) -> Self {
+        TokenContentType::String(val)
     }
 }
 
-impl Into<TokenContentType> for f64 {
-    fn into(self) -> TokenContentType {
-        TokenContentType::Number(self)
+impl From<f64> for TokenContentType {
+    fn from(val: f64) -> Self {
+        TokenContentType::Number(val)
     }
 }
 
-impl Into<TokenContentType> for bool {
-    fn into(self) -> TokenContentType {
-        TokenContentType::Boolean(self)
+impl From<bool> for TokenContentType {
+    fn from(val: bool) -> Self {
+        TokenContentType::Boolean(val)
     }
 }
 
-impl Into<TokenContentType> for i64 {
-    fn into(self) -> TokenContentType {
-        TokenContentType::Number(self as f64)
+impl From<i64> for TokenContentType {
+    fn from(val: i64) -> Self {
+        TokenContentType::Number(val as f64)
     }
 }
 
 impl From<Token> for String {
     fn from(token: Token) -> String {
         format!(
-            "Token {{ type: {:?},  value: {},  content: {:?}, line: {}, column: {} }}",
-            token._type, token.value, token.content, token.line, token.column
+            "Token {{ type: {:?},  value: {},  content: {:?}, span: {} }}",
+            token._type, token.value, token.content, token.span
         )
     }
 }
 
+/**
+ * A range of source text. Lines and columns are 1-based, columns count chars,
+ * and both ends are exclusive at the end (`end_byte` / `end_col` point just past the range).
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    // from the start of this span to the end of `end`
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            file: Rc::clone(&self.file),
+            start_byte: self.start_byte,
+            end_byte: end.end_byte,
+            start_line: self.start_line,
+            start_col: self.start_col,
+            end_line: end.end_line,
+            end_col: end.end_col,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.start_line, self.start_col)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Token {
     pub _type: TokenType,
     pub value: String,
     pub content: TokenContentType,
-    line: usize,
-    column: usize,
+    pub span: Span,
+    /**
+     * whitespace and comments between the previous token and this one,
+     * only recorded when the lexer was built `withTrivia`
+     */
+    pub trivia: Vec<Trivia>,
+    /**
+     * text of the `~~` doc comment lines right before this token
+     */
+    pub doc: Option<String>,
 }
 
 impl Token {
-    pub fn new(
-        _type: TokenType,
-        value: String,
-        content: TokenContentType,
-        line: usize,
-        column: usize,
-    ) -> Token {
+    pub fn new(_type: TokenType, value: String, content: TokenContentType, span: Span) -> Token {
         Token {
             _type,
             value,
             content,
-            line,
-            column,
+            span,
+            trivia: vec![],
+            doc: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    DocComment,
+}
+
+/**
+ * Source text the parser never sees. The trivia of every token followed by the token's own
+ * span reproduces the program exactly.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexErrorKind {
+    UnterminatedString,
+    UnexpectedCharacter(char),
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
+    MalformedNumber(String),
+    UnterminatedComment,
+}
+
+impl std::fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexErrorKind::UnterminatedString => write!(f, "Unterminated string"),
+            LexErrorKind::UnexpectedCharacter(c) => write!(f, "Unexpected character: {:?}", c),
+            LexErrorKind::InvalidEscape(c) => write!(f, "Invalid escape sequence: \\{}", c),
+            LexErrorKind::InvalidUnicodeEscape => {
+                write!(f, "Invalid unicode escape, expected \\u{{XXXX}}")
+            }
+            LexErrorKind::MalformedNumber(text) => write!(f, "Malformed number: {}", text),
+            LexErrorKind::UnterminatedComment => write!(f, "Unterminated block comment"),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+/**
+ * Lexes lazily: the lexer is an iterator of tokens (or errors), ending with a single EOF token.
+ * `scan_tokens` drains it when the whole program is wanted at once.
+ */
 pub struct Lexer {
     program: String,
-    pub tokens: Vec<Token>,
+    file: Rc<str>,
+    // byte offset of the next char to read
     current: usize,
     line: usize,
     column: usize,
+    // where the token currently being scanned started
+    startByte: usize,
+    startLine: usize,
+    startColumn: usize,
+    emittedEOF: bool,
+    keepTrivia: bool,
+    dialect: Dialect,
+    // collected since the last token, handed to the next one
+    trivia: Vec<Trivia>,
+    doc: Option<String>,
 }
 
 impl Lexer {
-    pub fn new(program: String) -> Lexer {
+    pub fn new(program: String, file: &str) -> Lexer {
         Lexer {
             program,
-            tokens: Vec::new(),
+            file: Rc::from(file),
             current: 0,
             line: 1,
             column: 1,
+            startByte: 0,
+            startLine: 1,
+            startColumn: 1,
+            emittedEOF: false,
+            keepTrivia: false,
+            dialect: Dialect::classic(),
+            trivia: vec![],
+            doc: None,
         }
     }
 
+    pub fn withDialect(mut self, dialect: Dialect) -> Lexer {
+        self.dialect = dialect;
+        self
+    }
+
+    /**
+     * Keep whitespace and comments on the tokens, for tools that need to reproduce the source.
+     */
+    pub fn withTrivia(mut self, keepTrivia: bool) -> Lexer {
+        self.keepTrivia = keepTrivia;
+        self
+    }
+
     pub fn isAtEnd(&self) -> bool {
         self.current >= self.program.len()
     }
 
     pub fn peek(&self) -> char {
-        if self.isAtEnd() {
-            return '\0';
-        }
-        self.program.chars().nth(self.current).unwrap()
+        self.program[self.current..].chars().next().unwrap_or('\0')
     }
 
     pub fn advance(&mut self) -> char {
-        if self.isAtEnd() {
+        let Some(ret) = self.program[self.current..].chars().next() else {
             return '\0';
-        }
+        };
 
-        self.column += 1;
-        let ret = self.program.chars().nth(self.current).unwrap();
-        self.current += 1;
+        self.current += ret.len_utf8();
+        if ret == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
 
         ret
     }
 
+    pub fn peekNext(&self) -> char {
+        self.program[self.current..].chars().nth(1).unwrap_or('\0')
+    }
+
     pub fn match_char(&mut self, expected: char) -> Option<char> {
         if self.peek() == expected {
             return Some(self.advance());
@@ -279,307 +511,760 @@ impl Lexer {
         None
     }
 
-    fn isChar(&self, c: char) -> bool {
-        c.is_alphabetic() || c == '_'
+    // identifiers follow Unicode's XID_Start / XID_Continue, with `_` allowed anywhere
+    fn isIdentifierStart(&self, c: char) -> bool {
+        UnicodeXID::is_xid_start(c) || c == '_'
+    }
+
+    fn isIdentifierContinue(&self, c: char) -> bool {
+        UnicodeXID::is_xid_continue(c)
+    }
+
+    // the span from the start of the current token up to the cursor
+    fn span(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            start_byte: self.startByte,
+            end_byte: self.current,
+            start_line: self.startLine,
+            start_col: self.startColumn,
+            end_line: self.line,
+            end_col: self.column,
+        }
+    }
+
+    fn token(
+        &self,
+        _type: TokenType,
+        value: &str,
+        content: TokenContentType,
+    ) -> Option<Result<Token, LexError>> {
+        Some(Ok(Token::new(
+            _type,
+            value.to_string(),
+            content,
+            self.span(),
+        )))
+    }
+
+    // punctuation and operators carry their own text as content
+    fn symbol(&self, _type: TokenType, symbol: &str) -> Option<Result<Token, LexError>> {
+        self.token(_type, symbol, symbol.to_string().into())
+    }
+
+    // records the text scanned since the token start as trivia, returning None like any trivia
+    fn trivia(&mut self, kind: TriviaKind) -> Option<Result<Token, LexError>> {
+        if self.keepTrivia {
+            let trivia = Trivia {
+                kind,
+                text: self.program[self.startByte..self.current].to_string(),
+                span: self.span(),
+            };
+            self.trivia.push(trivia);
+        }
+        None
+    }
+
+    /**
+     * `~* ... *~`, which may nest. The opening `~*` has already been consumed.
+     */
+    fn blockComment(&mut self) -> Option<Result<Token, LexError>> {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.isAtEnd() {
+                return self.error(LexErrorKind::UnterminatedComment);
+            }
+            let rest = &self.program[self.current..];
+            if rest.starts_with("~*") {
+                depth += 1;
+                self.advance();
+            } else if rest.starts_with("*~") {
+                depth -= 1;
+                self.advance();
+            }
+            self.advance();
+        }
+        self.trivia(TriviaKind::BlockComment)
+    }
+
+    fn error(&self, kind: LexErrorKind) -> Option<Result<Token, LexError>> {
+        Some(Err(LexError {
+            kind,
+            span: self.span(),
+        }))
+    }
+
+    /**
+     * Strings are quoted with ' or " and end at the line they start on. Tripled quotes
+     * ("""...""") may span lines and let the quote char appear unescaped inside, with a `\` at
+     * the end of a line joining it to the next, and an `r` prefix turns off escapes and allows
+     * newlines too.
+     * Unless the string is raw, `{expr}` embeds an expression and `\{` is a literal brace.
+     * The opening quote has already been consumed.
+     */
+    fn string(&mut self, quote: char, raw: bool) -> Option<Result<Token, LexError>> {
+        // scanning embedded expressions moves these, so put them back before building the token
+        let start = (self.startByte, self.startLine, self.startColumn);
+        let triple = self.peek() == quote && self.peekNext() == quote;
+        if triple {
+            self.advance();
+            self.advance();
+        }
+        let delimiter = quote.to_string().repeat(if triple { 3 } else { 1 });
+        // so a missing quote only costs its own line, not the rest of the program
+        let multiline = triple || raw;
+        let textStart = self.current;
+
+        let mut string = String::new();
+        let mut parts = vec![];
+        // an invalid escape doesn't stop the scan, so the rest of the string isn't misread as code
+        let mut firstError = None;
+        while !self.program[self.current..].starts_with(&delimiter) {
+            if self.isAtEnd() || (!multiline && self.peek() == '\n') {
+                (self.startByte, self.startLine, self.startColumn) = start;
+                return self.error(LexErrorKind::UnterminatedString);
+            }
+            let escapeStart = (self.current, self.line, self.column);
+            let char = self.advance();
+            if raw || (char != '\\' && char != '{') {
+                string.push(char);
+                continue;
+            }
+            if char == '{' {
+                parts.push(StringPart::Text(std::mem::take(&mut string)));
+                let (tokens, error) = self.interpolation(multiline);
+                parts.push(StringPart::Code(tokens));
+                if firstError.is_none() {
+                    firstError = error;
+                }
+                continue;
+            }
+            if self.peek() == '\n' {
+                // a line continuation, or in a one-line string, left for the check above to report
+                if multiline {
+                    self.advance();
+                }
+                continue;
+            }
+            match self.escape() {
+                Ok(c) => string.push(c),
+                Err(kind) => {
+                    if firstError.is_none() {
+                        let (start_byte, start_line, start_col) = escapeStart;
+                        firstError = Some(LexError {
+                            kind,
+                            span: Span {
+                                start_byte,
+                                start_line,
+                                start_col,
+                                ..self.span()
+                            },
+                        });
+                    }
+                }
+            }
+        }
+        let text = self.program[textStart..self.current].to_string();
+        for _ in 0..delimiter.len() {
+            self.advance();
+        }
+        (self.startByte, self.startLine, self.startColumn) = start;
+
+        if let Some(error) = firstError {
+            return Some(Err(error));
+        }
+        if parts.is_empty() {
+            return self.token(TokenType::String, &string, string.clone().into());
+        }
+        parts.push(StringPart::Text(string));
+        self.token(
+            TokenType::Interpolation,
+            &text,
+            TokenContentType::Interpolation(parts),
+        )
+    }
+
+    /**
+     * Scans the expression inside `{...}` of an interpolated string, up to and including the
+     * closing brace, which is replaced by EOF. Stops early at the end of the program, or of the
+     * line when the string can't span lines.
+     */
+    fn interpolation(&mut self, multiline: bool) -> (Vec<Token>, Option<LexError>) {
+        // trivia inside the braces is covered by the string token's span, so don't hand it on
+        let outerTrivia = std::mem::take(&mut self.trivia);
+        let outerDoc = self.doc.take();
+        let mut tokens = vec![];
+        let mut firstError = None;
+        let mut depth = 0;
+        while !self.isAtEnd() && (multiline || self.peek() != '\n') {
+            let token = match self.scan_token() {
+                Some(Ok(token)) => token,
+                Some(Err(error)) => {
+                    firstError.get_or_insert(error);
+                    continue;
+                }
+                None => continue,
+            };
+            match token._type {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => {
+                    tokens.push(Token::new(
+                        TokenType::EOF,
+                        String::from(""),
+                        String::from("").into(),
+                        token.span,
+                    ));
+                    break;
+                }
+                TokenType::RightBrace => depth -= 1,
+                _ => {}
+            }
+            tokens.push(token);
+        }
+        self.trivia = outerTrivia;
+        self.doc = outerDoc;
+        (tokens, firstError)
+    }
+
+    /**
+     * Number literals: `42`, `1_000_000`, `3.14`, `.5`, `6.02e23`, `1e-9`, `0xFF` and `0b1010`.
+     * A `.` only belongs to the number when a digit follows it, so `1.` is `1` then a period.
+     * The token keeps the literal text as its value and the parsed number as its content.
+     */
+    fn number(&mut self, first: char) -> Option<Result<Token, LexError>> {
+        let mut text = String::from(first);
+        // `text` without underscores, in a form Rust can parse
+        let mut digits = String::new();
+
+        let value = if first == '0' && matches!(self.peek(), 'x' | 'X' | 'b' | 'B') {
+            let prefix = self.advance();
+            text.push(prefix);
+            let radix = if prefix.eq_ignore_ascii_case(&'x') {
+                16
+            } else {
+                2
+            };
+            self.digits(radix, &mut text, &mut digits);
+            u64::from_str_radix(&digits, radix).ok().map(|n| n as f64)
+        } else {
+            if first == '.' {
+                digits.push_str("0.");
+            } else {
+                digits.push(first);
+            }
+            self.digits(10, &mut text, &mut digits);
+            if first != '.' && self.peek() == '.' && self.peekNext().is_ascii_digit() {
+                text.push(self.advance());
+                digits.push('.');
+                self.digits(10, &mut text, &mut digits);
+            }
+            if matches!(self.peek(), 'e' | 'E') {
+                text.push(self.advance());
+                digits.push('e');
+                if matches!(self.peek(), '+' | '-') {
+                    let sign = self.advance();
+                    text.push(sign);
+                    digits.push(sign);
+                }
+                if self.digits(10, &mut text, &mut digits) == 0 {
+                    digits.clear();
+                }
+            }
+            digits.parse::<f64>().ok()
+        };
+
+        // things like `0b102`, `12px` or `1_` are one bad literal, not a number and a name
+        let mut malformed = text.ends_with('_');
+        while self.isIdentifierContinue(self.peek()) {
+            text.push(self.advance());
+            malformed = true;
+        }
+        match value {
+            Some(value) if !malformed => self.token(TokenType::Number, &text, value.into()),
+            _ => self.error(LexErrorKind::MalformedNumber(text)),
+        }
+    }
+
+    // consumes digits of `radix` and underscores, returning how many digits there were
+    fn digits(&mut self, radix: u32, text: &mut String, digits: &mut String) -> usize {
+        let mut count = 0;
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            let char = self.advance();
+            text.push(char);
+            if char != '_' {
+                digits.push(char);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // the char after a backslash
+    fn escape(&mut self) -> Result<char, LexErrorKind> {
+        match self.advance() {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            '{' => Ok('{'),
+            '}' => Ok('}'),
+            'u' => {
+                if self.match_char('{').is_none() {
+                    return Err(LexErrorKind::InvalidUnicodeEscape);
+                }
+                let mut hex = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    hex.push(self.advance());
+                }
+                if self.match_char('}').is_none() || hex.is_empty() || hex.len() > 6 {
+                    return Err(LexErrorKind::InvalidUnicodeEscape);
+                }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(LexErrorKind::InvalidUnicodeEscape)
+            }
+            other => Err(LexErrorKind::InvalidEscape(other)),
+        }
     }
 
     /**
-     * Might have to return the token that gets pushed
+     * Scans the next lexeme. Whitespace and comments produce `None`.
      */
-    pub fn scan_token(&mut self) {
+    pub fn scan_token(&mut self) -> Option<Result<Token, LexError>> {
+        self.startByte = self.current;
+        self.startLine = self.line;
+        self.startColumn = self.column;
         let char = self.advance();
 
         match char {
-            '(' => self.tokens.push(Token::new(
-                TokenType::LeftParen,
-                String::from("("),
-                String::from("(").into(),
-                self.line,
-                self.column,
-            )),
-            ')' => self.tokens.push(Token::new(
-                TokenType::RightParen,
-                String::from(")"),
-                String::from(")").into(),
-                self.line,
-                self.column,
-            )),
-            '{' => self.tokens.push(Token::new(
-                TokenType::LeftBrace,
-                String::from("{"),
-                String::from("{").into(),
-                self.line,
-                self.column,
-            )),
-            '}' => self.tokens.push(Token::new(
-                TokenType::RightBrace,
-                String::from("}"),
-                String::from("}").into(),
-                self.line,
-                self.column,
-            )),
-            '[' => self.tokens.push(Token::new(
-                TokenType::LeftBracket,
-                String::from("["),
-                String::from("[").into(),
-                self.line,
-                self.column,
-            )),
-            ']' => self.tokens.push(Token::new(
-                TokenType::RightBracket,
-                String::from("]"),
-                String::from("]").into(),
-                self.line,
-                self.column,
-            )),
-            '.' => self.tokens.push(Token::new(
-                TokenType::Period,
-                String::from("."),
-                String::from(".").into(),
-                self.line,
-                self.column,
-            )),
-            ',' => self.tokens.push(Token::new(
-                TokenType::Comma,
-                String::from(","),
-                String::from(",").into(),
-                self.line,
-                self.column,
-            )),
-            ':' => self.tokens.push(Token::new(
-                TokenType::Colon,
-                String::from(":"),
-                String::from(":").into(),
-                self.line,
-                self.column,
-            )),
-            '+' => self.tokens.push(Token::new(
-                TokenType::Plus,
-                String::from("+"),
-                String::from("+").into(),
-                self.line,
-                self.column,
-            )),
-            '-' => self.tokens.push(Token::new(
-                TokenType::Minus,
-                String::from("-"),
-                String::from("-").into(),
-                self.line,
-                self.column,
-            )),
-            '*' => self.tokens.push(Token::new(
-                TokenType::Asterisk,
-                String::from("*"),
-                String::from("*").into(),
-                self.line,
-                self.column,
-            )),
-            '/' => self.tokens.push(Token::new(
-                TokenType::Slash,
-                String::from("/"),
-                String::from("/").into(),
-                self.line,
-                self.column,
-            )),
-            '\'' | '"' => {
-                let mut string = String::new();
-                while self.peek() != char {
-                    string.push(self.advance());
-                    if self.isAtEnd() {
-                        panic!("Unterminated string");
-                    }
+            '(' => self.symbol(TokenType::LeftParen, "("),
+            ')' => self.symbol(TokenType::RightParen, ")"),
+            '{' => self.symbol(TokenType::LeftBrace, "{"),
+            '}' => self.symbol(TokenType::RightBrace, "}"),
+            '[' => self.symbol(TokenType::LeftBracket, "["),
+            ']' => self.symbol(TokenType::RightBracket, "]"),
+            '.' => {
+                if self.peek().is_ascii_digit() {
+                    self.number(char)
+                } else if self.peek() == '.' && self.peekNext() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.symbol(TokenType::Ellipsis, "...")
+                } else {
+                    self.symbol(TokenType::Period, ".")
+                }
+            }
+            ',' => self.symbol(TokenType::Comma, ","),
+            ':' => self.symbol(TokenType::Colon, ":"),
+            '+' => {
+                if self.match_char('=').is_some() {
+                    self.symbol(TokenType::PlusAssign, "+=")
+                } else {
+                    self.symbol(TokenType::Plus, "+")
+                }
+            }
+            '-' => {
+                if self.match_char('=').is_some() {
+                    self.symbol(TokenType::MinusAssign, "-=")
+                } else {
+                    self.symbol(TokenType::Minus, "-")
+                }
+            }
+            '%' => {
+                if self.match_char('=').is_some() {
+                    self.symbol(TokenType::ModuloAssign, "%=")
+                } else {
+                    self.symbol(TokenType::Modulo, "%")
                 }
-                self.advance();
-                self.tokens.push(Token::new(
-                    TokenType::String,
-                    string.clone(),
-                    string.into(),
-                    self.line,
-                    self.column,
-                ));
             }
+            '^' => self.symbol(TokenType::BitXor, "^"),
+            '*' => {
+                if self.match_char('*').is_some() {
+                    self.symbol(TokenType::Power, "**")
+                } else if self.match_char('=').is_some() {
+                    self.symbol(TokenType::AsteriskAssign, "*=")
+                } else {
+                    self.symbol(TokenType::Asterisk, "*")
+                }
+            }
+            '/' => {
+                if self.match_char('/').is_some() {
+                    self.symbol(TokenType::FloorDiv, "//")
+                } else if self.match_char('=').is_some() {
+                    self.symbol(TokenType::SlashAssign, "/=")
+                } else {
+                    self.symbol(TokenType::Slash, "/")
+                }
+            }
+            '\'' | '"' => self.string(char, false),
             '|' => {
                 if self.match_char('|').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::Or,
-                        String::from("||"),
-                        String::from("||").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Or, "||")
+                } else {
+                    self.symbol(TokenType::BitOr, "|")
                 }
             }
             '>' => {
                 if self.match_char('=').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::Gte,
-                        String::from(">="),
-                        String::from(">=").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Gte, ">=")
+                } else if self.match_char('>').is_some() {
+                    self.symbol(TokenType::ShiftRight, ">>")
                 } else {
-                    self.tokens.push(Token::new(
-                        TokenType::Gt,
-                        String::from(">"),
-                        String::from(">").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Gt, ">")
                 }
             }
             '<' => {
                 if self.match_char('=').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::Lte,
-                        String::from("<="),
-                        String::from("<=").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Lte, "<=")
+                } else if self.match_char('<').is_some() {
+                    self.symbol(TokenType::ShiftLeft, "<<")
                 } else {
-                    self.tokens.push(Token::new(
-                        TokenType::Lt,
-                        String::from("<"),
-                        String::from("<").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Lt, "<")
                 }
             }
             '=' => {
                 if self.match_char('=').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::Equiv,
-                        String::from("=="),
-                        String::from("==").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Equiv, "==")
                 } else {
-                    self.tokens.push(Token::new(
-                        TokenType::Keyword,
-                        String::from("="),
-                        String::from("=").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Keyword, "=")
                 }
             }
             '&' => {
                 if self.match_char('&').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::And,
-                        String::from("&&"),
-                        String::from("&&").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::And, "&&")
+                } else {
+                    self.symbol(TokenType::BitAnd, "&")
                 }
             }
             '!' => {
                 if self.match_char('=').is_some() {
-                    self.tokens.push(Token::new(
-                        TokenType::NotEquiv,
-                        String::from("!="),
-                        String::from("!=").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::NotEquiv, "!=")
                 } else {
-                    self.tokens.push(Token::new(
-                        TokenType::Not,
-                        String::from("!"),
-                        String::from("!").into(),
-                        self.line,
-                        self.column,
-                    ));
+                    self.symbol(TokenType::Not, "!")
                 }
             }
             '~' => {
                 // comments!
+                if self.match_char('*').is_some() {
+                    return self.blockComment();
+                }
+                let isDoc = self.match_char('~').is_some();
                 while self.peek() != '\n' && self.peek() != '\0' {
                     self.advance();
                 }
-                return;
-            }
-            ' ' | '\r' => {
-                return;
+                if !isDoc {
+                    return self.trivia(TriviaKind::LineComment);
+                }
+
+                let text = self.program[self.startByte + 2..self.current].trim_end();
+                let text = text.strip_prefix(' ').unwrap_or(text).to_string();
+                self.doc = Some(match self.doc.take() {
+                    Some(doc) => format!("{}\n{}", doc, text),
+                    None => text,
+                });
+                self.trivia(TriviaKind::DocComment)
             }
-            '\n' => {
-                self.line += 1;
-                self.column = 0;
-                return;
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+                self.trivia(TriviaKind::Whitespace)
             }
+            '\n' => self.trivia(TriviaKind::Newline),
             _ => {
-                if char.is_numeric() {
-                    let mut number = String::from("");
-                    number.push(char);
-                    let mut char = self.peek();
-                    // parse decimals, too
-                    while char.is_numeric() || (char == '.' && !number.contains('.')) {
-                        number.push(char);
-                        self.advance();
-                        char = self.peek();
-                    }
-                    self.tokens.push(Token::new(
-                        TokenType::Number,
-                        number.clone(),
-                        number.parse::<f64>().unwrap().into(),
-                        self.line,
-                        self.column,
-                    ));
-                } else if self.isChar(char) {
+                if char.is_ascii_digit() {
+                    self.number(char)
+                } else if char == 'r' && matches!(self.peek(), '\'' | '"') {
+                    let quote = self.advance();
+                    self.string(quote, true)
+                } else if self.isIdentifierStart(char) {
                     let mut identifier = String::from("");
                     identifier.push(char);
-                    let mut char = self.peek();
-                    while char.is_alphanumeric() {
-                        identifier.push(char);
-                        self.advance();
-                        char = self.peek();
+                    while self.isIdentifierContinue(self.peek()) {
+                        identifier.push(self.advance());
                     }
+                    // NFC, so `é` typed as one code point or as `e` plus an accent is one name
+                    let identifier: String = identifier.nfc().collect();
 
-                    let _type = if KEYWORDS().contains(&identifier.as_str()) {
-                        TokenType::Keyword
+                    // keywords are reported under their canonical name
+                    if let Some(keyword) = self.dialect.keyword(&identifier) {
+                        self.symbol(TokenType::Keyword, keyword)
                     } else if identifier == "true" || identifier == "false" {
-                        TokenType::Boolean
+                        self.token(
+                            TokenType::Boolean,
+                            &identifier,
+                            (identifier == "true").into(),
+                        )
                     } else {
-                        TokenType::Identifier
-                    };
-
-                    // println!("Identifier: {}", identifier);
-                    self.tokens.push(Token::new(
-                        _type,
-                        identifier.clone(),
-                        if identifier == "true" {
-                            true.into()
-                        } else if identifier == "false" {
-                            false.into()
-                        } else {
-                            identifier.into()
-                        },
-                        self.line,
-                        self.column,
-                    ));
+                        self.symbol(TokenType::Identifier, &identifier)
+                    }
                 } else {
-                    panic!("Unexpected character: {}", char);
+                    // keep going so every bad character in the file gets reported
+                    self.error(LexErrorKind::UnexpectedCharacter(char))
                 }
             }
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.isAtEnd() {
-            self.scan_token();
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<LexError>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for result in self.by_ref() {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(tokens)
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut result = None;
+        while result.is_none() && !self.isAtEnd() {
+            result = self.scan_token();
         }
-        self.tokens.push(Token::new(
-            TokenType::EOF,
-            String::from(""),
-            String::from("").into(),
-            self.line,
-            self.column,
-        ));
 
-        self.tokens.clone()
+        if result.is_none() {
+            if self.emittedEOF {
+                return None;
+            }
+            self.emittedEOF = true;
+            self.startByte = self.current;
+            self.startLine = self.line;
+            self.startColumn = self.column;
+            result = self.token(TokenType::EOF, "", String::from("").into());
+        }
+
+        if let Some(Ok(token)) = &mut result {
+            token.trivia = std::mem::take(&mut self.trivia);
+            token.doc = self.doc.take();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a bit of everything: a doc comment, block and line comments, a string with code in it
+    const PROGRAM: &str = "~~ adds two numbers\nfunc add needs (a, b) {\n    finished a + b ~ the sum\n}\n~* a block ~* nested *~ comment *~\nlet s = \"1 + 2 is {add(1, 2)}\"\nprint(s.length)\n";
+
+    fn lexer(program: &str) -> Lexer {
+        Lexer::new(program.to_string(), "test.txt").withTrivia(true)
+    }
+
+    // everything about a token but the trivia before it
+    fn summary(token: &Token) -> (TokenType, String, Span) {
+        (token._type, token.value.clone(), token.span.clone())
+    }
+
+    #[test]
+    fn tokenTypesAndSpans() {
+        let tokens = lexer("let x = 1.5 + y\nprint(x) ~ done\n")
+            .scan_tokens()
+            .unwrap();
+        let found: Vec<(TokenType, &str, usize, usize, usize)> = tokens
+            .iter()
+            .map(|token| {
+                let span = &token.span;
+                (
+                    token._type,
+                    token.value.as_str(),
+                    span.start_line,
+                    span.start_col,
+                    span.end_col,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (TokenType::Keyword, "let", 1, 1, 4),
+                (TokenType::Identifier, "x", 1, 5, 6),
+                (TokenType::Keyword, "=", 1, 7, 8),
+                (TokenType::Number, "1.5", 1, 9, 12),
+                (TokenType::Plus, "+", 1, 13, 14),
+                (TokenType::Identifier, "y", 1, 15, 16),
+                (TokenType::Identifier, "print", 2, 1, 6),
+                (TokenType::LeftParen, "(", 2, 6, 7),
+                (TokenType::Identifier, "x", 2, 7, 8),
+                (TokenType::RightParen, ")", 2, 8, 9),
+                (TokenType::EOF, "", 3, 1, 1),
+            ]
+        );
+        let print = &tokens[6].span;
+        assert_eq!((print.start_byte, print.end_byte), (16, 21));
+        assert_eq!(&*print.file, "test.txt");
+    }
+
+    #[test]
+    fn iteratorMatchesScanTokens() {
+        let scanned = lexer(PROGRAM).scan_tokens().unwrap();
+        let streamed = lexer(PROGRAM)
+            .collect::<Result<Vec<Token>, LexError>>()
+            .unwrap();
+        assert_eq!(
+            scanned.iter().map(summary).collect::<Vec<_>>(),
+            streamed.iter().map(summary).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn oneEOFAtTheEnd() {
+        for program in ["", "   \n", PROGRAM] {
+            let mut lexer = lexer(program);
+            let tokens = lexer
+                .by_ref()
+                .collect::<Result<Vec<Token>, LexError>>()
+                .unwrap();
+            let eofs = tokens
+                .iter()
+                .filter(|token| token._type == TokenType::EOF)
+                .count();
+            assert_eq!(eofs, 1, "in {:?}", program);
+            assert_eq!(tokens.last().unwrap()._type, TokenType::EOF);
+            assert!(lexer.next().is_none());
+        }
+    }
+
+    #[test]
+    fn triviaRoundTrips() {
+        let tokens = lexer(PROGRAM).scan_tokens().unwrap();
+        let mut source = String::new();
+        for token in &tokens {
+            for trivia in &token.trivia {
+                source.push_str(&trivia.text);
+            }
+            source.push_str(&PROGRAM[token.span.start_byte..token.span.end_byte]);
+        }
+        assert_eq!(source, PROGRAM);
+    }
+
+    // the text of every string token
+    fn strings(program: &str) -> Vec<String> {
+        lexer(program)
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token.content {
+                TokenContentType::String(text) if token._type == TokenType::String => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn errors(program: &str) -> Vec<String> {
+        lexer(program)
+            .scan_tokens()
+            .unwrap_err()
+            .iter()
+            .map(LexError::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn stringEscapes() {
+        assert_eq!(
+            strings(r#""a\tb\n\\ \"q\" \u{e9} \{x\}" 'it\'s\0'"#),
+            vec!["a\tb\n\\ \"q\" é {x}", "it's\0"]
+        );
+        assert_eq!(
+            errors(r#"let s = "a\qb\u{zz}""#),
+            vec!["test.txt:1:11: Invalid escape sequence: \\q"]
+        );
+        assert_eq!(
+            errors(r#""\u{110000}""#),
+            vec!["test.txt:1:2: Invalid unicode escape, expected \\u{XXXX}"]
+        );
+    }
+
+    #[test]
+    fn rawAndTripleQuotedStrings() {
+        assert_eq!(
+            strings("r\"a\\n{b}\nc\" \"\"\"say \"hi\"\nthere\"\"\" '''one \\\ntwo'''"),
+            vec!["a\\n{b}\nc", "say \"hi\"\nthere", "one two"]
+        );
+    }
+
+    #[test]
+    fn ordinaryStringsEndAtTheLine() {
+        let results: Vec<Result<Token, LexError>> =
+            lexer("let s = \"abc\nlet t = 'a\\\n'\nprint(t)\n").collect();
+        let errors: Vec<String> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err().map(LexError::to_string))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "test.txt:1:9: Unterminated string",
+                "test.txt:2:9: Unterminated string",
+                "test.txt:3:1: Unterminated string",
+            ]
+        );
+        // the lines after each one are still lexed
+        let names: Vec<&str> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .filter(|token| token._type == TokenType::Identifier)
+            .map(|token| token.value.as_str())
+            .collect();
+        assert_eq!(names, vec!["s", "t", "print", "t"]);
+    }
+
+    #[test]
+    fn numberForms() {
+        let numbers: Vec<f64> = lexer("1_000 0x1F 0B101 1.5e3 .5 2E-2 7")
+            .scan_tokens()
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token.content {
+                TokenContentType::Number(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(numbers, vec![1000.0, 31.0, 5.0, 1500.0, 0.5, 0.02, 7.0]);
+
+        for program in ["0x", "1e", "12px", "1_", "0b102"] {
+            assert_eq!(
+                errors(program),
+                vec![format!("test.txt:1:1: Malformed number: {}", program)]
+            );
+        }
+    }
+
+    #[test]
+    fn dialectsRenameKeywordsButNotNames() {
+        let tokens = Lexer::new("prepare add as ink".to_string(), "test.txt")
+            .withDialect(Dialect::sketch())
+            .scan_tokens()
+            .unwrap();
+        let found: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .map(|token| (token._type, token.value.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (TokenType::Keyword, "let"),
+                (TokenType::Identifier, "add"),
+                (TokenType::Keyword, "="),
+                (TokenType::Identifier, "ink"),
+                (TokenType::EOF, ""),
+            ]
+        );
     }
 }
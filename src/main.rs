@@ -1,29 +1,109 @@
+#![allow(non_snake_case, clippy::upper_case_acronyms)]
+
 mod ast;
+mod dialect;
+mod environment;
 mod interpreter;
 mod lexer;
 mod parser;
 mod stdlib;
+mod value;
 
-use std::{cell::RefCell, collections::HashMap, env, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, env, rc::Rc, thread};
 
-use ast::{Array, Ast, Literal};
-use interpreter::Interpreter;
-use lexer::TokenContentType;
+use ast::Ast;
+use dialect::Dialect;
+use environment::Environment;
+use interpreter::{ErrorKind, Interpreter, RuntimeError, StructScope, DEFAULT_MAX_DEPTH};
+use lexer::Span;
+use value::Value;
 
-// use interpreter::Interpreter;
+// stack for everything but script calls, and then for each script call on top of that
+const BASE_STACK_SIZE: usize = 8 * 1024 * 1024;
+const STACK_PER_CALL: usize = 256 * 1024;
 
 fn read_file(location: &str) -> String {
-    std::fs::read_to_string(location)
-        .expect("Failed to read file")
-        .trim()
-        .to_string()
+    match std::fs::read_to_string(location) {
+        Ok(program) => program.trim().to_string(),
+        Err(error) => {
+            eprintln!("Failed to read {}: {}", location, error);
+            std::process::exit(1);
+        }
+    }
 }
 
 #[allow(dead_code)]
-fn write_file(location: &str, data: &str) -> () {
+fn write_file(location: &str, data: &str) {
     std::fs::write(location, data).expect("Failed to write file");
 }
 
+// the source line containing `span`, with the span marked underneath
+fn underline(program: &str, span: &Span) -> String {
+    let lineStart = program[..span.start_byte].rfind('\n').map_or(0, |i| i + 1);
+    let lineEnd = program[span.start_byte..]
+        .find('\n')
+        .map_or(program.len(), |i| span.start_byte + i);
+    let padding = program[lineStart..span.start_byte].chars().count();
+    let width = program[span.start_byte..span.end_byte.min(lineEnd)]
+        .chars()
+        .count()
+        .max(1);
+    format!(
+        "    {}\n    {}{}",
+        &program[lineStart..lineEnd],
+        " ".repeat(padding),
+        "^".repeat(width)
+    )
+}
+
+// the error for a builtin that was given arguments it can't use
+fn badArguments(function: &str, expected: &str, args: &[Value]) -> RuntimeError {
+    let given = args
+        .iter()
+        .map(Value::typeName)
+        .collect::<Vec<&str>>()
+        .join(", ");
+    RuntimeError::new(
+        ErrorKind::Argument,
+        format!("{} expects {}, got ({})", function, expected, given),
+    )
+}
+
+// lexes and parses one source file, reporting every lex or parse error and exiting if there are any
+fn parseSource(program: &str, location: &str, dialect: &Dialect, debug: bool) -> Vec<Ast> {
+    let mut lexer = lexer::Lexer::new(program.to_string(), location)
+        .withTrivia(debug)
+        .withDialect(dialect.clone());
+    let tokens = match lexer.scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", error);
+                eprintln!("{}", underline(program, &error.span));
+            }
+            std::process::exit(1);
+        }
+    };
+
+    if debug {
+        write_file(
+            "tokens.txt",
+            format!("{:#?}", serde_json::to_string(&tokens.clone()).unwrap()).as_str(),
+        );
+    }
+
+    let mut parser = parser::Parser::new(tokens);
+    let (ast, errors) = parser.parse();
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("{}", error);
+            eprintln!("{}", underline(program, &error.span));
+        }
+        std::process::exit(1);
+    }
+    ast
+}
+
 fn main() {
     let mut argv = env::args().collect::<Vec<String>>();
     let debug = argv.iter().any(|x| x == "--dbg");
@@ -34,6 +114,68 @@ fn main() {
         argv.retain(|x| x != "--dbg");
     }
 
+    // --dialect=NAME or --dialect NAME, which wins over a pragma in the file
+    let mut dialectName = None;
+    if let Some(i) = argv.iter().position(|x| x.starts_with("--dialect")) {
+        let flag = argv.remove(i);
+        dialectName = match flag.strip_prefix("--dialect=") {
+            Some(name) => Some(name.to_string()),
+            None if flag == "--dialect" && i < argv.len() => Some(argv.remove(i)),
+            None => {
+                eprintln!("Expected a dialect name after {}", flag);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    // --max-depth=N or --max-depth N, how deeply functions can call each other
+    let mut maxDepth = DEFAULT_MAX_DEPTH;
+    if let Some(i) = argv.iter().position(|x| x.starts_with("--max-depth")) {
+        let flag = argv.remove(i);
+        let depth = match flag.strip_prefix("--max-depth=") {
+            Some(depth) => Some(depth.to_string()),
+            None if flag == "--max-depth" && i < argv.len() => Some(argv.remove(i)),
+            None => None,
+        };
+        maxDepth = match depth.and_then(|depth| depth.parse().ok()) {
+            Some(depth) => depth,
+            None => {
+                eprintln!("Expected a number of calls after {}", flag);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    /*
+     * Every nested script call nests several Rust frames too, far more than the main thread's
+     * stack has room for, so the program runs on a thread with enough stack for `maxDepth`
+     * typical calls. The interpreter watches how much of it is left in case they aren't typical.
+     */
+    let stackSize = maxDepth
+        .saturating_mul(STACK_PER_CALL)
+        .saturating_add(BASE_STACK_SIZE);
+    let interpreter = thread::Builder::new().stack_size(stackSize).spawn(move || {
+        Interpreter::setMaxDepth(maxDepth);
+        Interpreter::setStackSize(stackSize);
+        runFile(argv, debug, dialectName);
+    });
+    let interpreter = match interpreter {
+        Ok(interpreter) => interpreter,
+        Err(error) => {
+            eprintln!(
+                "Not enough memory for a max depth of {}: {}",
+                maxDepth, error
+            );
+            std::process::exit(1);
+        }
+    };
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+// runs the program named in `argv`, with the flags already taken out
+fn runFile(argv: Vec<String>, debug: bool, dialectName: Option<String>) {
     let location = argv.get(1);
 
     match location {
@@ -42,19 +184,23 @@ fn main() {
                 println!("Reading file: {}", location);
             }
             let program = read_file(location);
-            let mut lexer = lexer::Lexer::new(program);
-            let tokens = lexer.scan_tokens();
 
+            let dialectName = dialectName
+                .or_else(|| Dialect::pragma(&program).map(str::to_string))
+                .unwrap_or_else(|| Dialect::default().name.to_string());
+            let dialect = match Dialect::named(&dialectName) {
+                Some(dialect) => dialect,
+                None => {
+                    eprintln!("Unknown dialect: {}", dialectName);
+                    std::process::exit(1);
+                }
+            };
             if debug {
-                write_file(
-                    "tokens.txt",
-                    format!("{:#?}", serde_json::to_string(&tokens.clone()).unwrap()).as_str(),
-                );
+                println!("Dialect: {}", dialect.name);
             }
 
-            let mut parser = parser::Parser::new(tokens);
-
-            let ast = parser.parse();
+            let mut ast = parseSource(dialect.prelude, "<prelude>", &dialect, false);
+            ast.extend(parseSource(&program, location, &dialect, debug));
 
             if debug {
                 write_file(
@@ -68,157 +214,156 @@ fn main() {
             }
             // println!("{}", program);
 
-            let standardLibraryFunctions: Rc<
-                RefCell<HashMap<String, Box<dyn Fn(Vec<Ast>) -> Ast>>>,
-            > = Rc::new(RefCell::new(HashMap::new()));
-
-            let scope = Rc::new(RefCell::new(HashMap::new()));
-            let structScope = Rc::new(RefCell::new(HashMap::new()));
+            let scope = Environment::global();
+            let structScope: StructScope = Rc::new(RefCell::new(HashMap::new()));
 
-            let borrowedScope = Rc::clone(&scope);
-            let borrowedStandardLibraryFunctions = Rc::clone(&standardLibraryFunctions);
-            let borrowedStructScope = Rc::clone(&structScope);
-
-            standardLibraryFunctions.borrow_mut().insert(
+            scope.borrow_mut().declare(
                 "print".to_string(),
-                Box::new(move |args: Vec<Ast>| {
-                    let scope = Rc::clone(&borrowedScope);
-                    let standardLibraryFunctions = Rc::clone(&borrowedStandardLibraryFunctions);
-                    let structScope = Rc::clone(&borrowedStructScope);
+                Value::native("print", |args| {
                     for arg in args {
-                        println!(
-                            "{}",
-                            Interpreter::toPrint(
-                                arg,
-                                Rc::clone(&scope),
-                                Rc::clone(&standardLibraryFunctions),
-                                Rc::clone(&structScope)
-                            )
-                        );
+                        println!("{}", arg);
                     }
-                    Ast::None
+                    Ok(Value::None)
                 }),
             );
 
-            standardLibraryFunctions.borrow_mut().insert(
+            scope.borrow_mut().declare(
                 "input".to_string(),
-                Box::new(move |_| {
+                Value::native("input", |_| {
                     let mut input = String::new();
-                    std::io::stdin()
-                        .read_line(&mut input)
-                        .expect("Failed to read line");
-                    Ast::Literal(Literal {
-                        content: input.trim().to_string().into(),
-                    })
+                    std::io::stdin().read_line(&mut input).map_err(|error| {
+                        RuntimeError::new(
+                            ErrorKind::Input,
+                            format!("Failed to read line: {}", error),
+                        )
+                    })?;
+                    Ok(Value::String(input.trim().to_string()))
                 }),
             );
 
-            // array functions
-            standardLibraryFunctions.borrow_mut().insert(
-                "STDLIB_ARRAY_PUSH".to_string(),
-                Box::new(move |args| {
-                    // println!("ARGS IN ARRAY PUSH {:?}", args);
-
-                    return match args.get(0) {
-                        Some(Ast::Array(array)) => {
-                            let mut array = array.clone();
-                            array.content.push(args.get(1).unwrap().clone());
-                            Ast::Array(array)
-                        }
-                        _ => panic!("Expected array as first argument"),
+            scope.borrow_mut().declare(
+                "random".to_string(),
+                Value::native("random", |args| {
+                    let bounds = args
+                        .iter()
+                        .map(|arg| match arg {
+                            Value::Number(n) => Some(*n),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<f64>>>();
+                    let (min, max) = match bounds.as_deref() {
+                        Some([]) => (0.0, 1.0),
+                        Some([max]) => (0.0, *max),
+                        Some([min, max]) => (*min, *max),
+                        _ => return Err(badArguments("random", "up to 2 numbers", &args)),
                     };
+                    Ok(Value::Number(stdlib::random(min, max)))
                 }),
             );
 
-            standardLibraryFunctions.borrow_mut().insert(
-                "STDLIB_ARRAY_POP".to_string(),
-                Box::new(move |args| {
-                    // println!("ARGS IN ARRAY POP {:?}", args);
+            if dialect.canvas {
+                // there is no window to draw in yet, so the canvas describes what it would draw
+                let fields = ["fill", "erase"]
+                    .into_iter()
+                    .map(|method| {
+                        let drawing = Value::native(method, move |args| {
+                            let args = args.iter().map(Value::to_string).collect::<Vec<String>>();
+                            println!("canvas {} {}", method, args.join(" "));
+                            Ok(Value::None)
+                        });
+                        (method.to_string(), drawing)
+                    })
+                    .collect();
+                scope
+                    .borrow_mut()
+                    .declare("Canvas".to_string(), Value::instance("Canvas", fields));
+            }
 
-                    return match args.get(0) {
-                        Some(Ast::Array(array)) => {
-                            let mut array = array.clone();
-                            array.content.pop();
-                            Ast::Array(array)
-                        }
-                        _ => panic!("Expected array as first argument"),
-                    };
+            // higher-order functions, taking any function value (named or a lambda)
+            let borrowedStructScope = Rc::clone(&structScope);
+            scope.borrow_mut().declare(
+                "map".to_string(),
+                Value::native("map", move |args| match &args[..] {
+                    // the items are copied out first, in case the function changes the array
+                    [Value::Array(items), function] => Ok(Value::array(
+                        items
+                            .borrow()
+                            .clone()
+                            .into_iter()
+                            .map(|item| {
+                                Interpreter::call(
+                                    function.clone(),
+                                    vec![item],
+                                    Rc::clone(&borrowedStructScope),
+                                )
+                            })
+                            .collect::<Result<Vec<Value>, RuntimeError>>()?,
+                    )),
+                    _ => Err(badArguments("map", "an array and a function", &args)),
                 }),
             );
 
-            // reverse
-            standardLibraryFunctions.borrow_mut().insert(
-                "STDLIB_ARRAY_REVERSE".to_string(),
-                Box::new(move |args| {
-                    // println!("ARGS IN ARRAY REVERSE {:?}", args);
-
-                    return match args.get(0) {
-                        Some(Ast::Array(array)) => {
-                            let mut array = array.clone();
-                            array.content.reverse();
-                            Ast::Array(array)
+            let borrowedStructScope = Rc::clone(&structScope);
+            scope.borrow_mut().declare(
+                "filter".to_string(),
+                Value::native("filter", move |args| match &args[..] {
+                    [Value::Array(items), function] => {
+                        let mut kept = vec![];
+                        for item in items.borrow().clone() {
+                            let keep = Interpreter::call(
+                                function.clone(),
+                                vec![item.clone()],
+                                Rc::clone(&borrowedStructScope),
+                            )?;
+                            if matches!(keep, Value::Bool(true)) {
+                                kept.push(item);
+                            }
                         }
-                        _ => panic!("Expected array as first argument"),
-                    };
+                        Ok(Value::array(kept))
+                    }
+                    _ => Err(badArguments("filter", "an array and a function", &args)),
                 }),
             );
 
-            // sort
-            standardLibraryFunctions.borrow_mut().insert(
-                "STDLIB_ARRAY_SORT".to_string(),
-                Box::new(move |args| {
-                    // println!("ARGS IN ARRAY SORT {:?}", args);
-
-                    return match args.get(0) {
-                        Some(Ast::Array(array)) => {
-                            let mut array = array.clone();
-                            array.content.sort_by(|a, b| {
-                                let ab = match a {
-                                    Ast::Literal(Literal {
-                                        content: TokenContentType::Number(a),
-                                    }) => Some(a),
-                                    _ => None,
-                                };
-                                let bb = match b {
-                                    Ast::Literal(Literal {
-                                        content: TokenContentType::Number(b),
-                                    }) => Some(b),
-                                    _ => None,
-                                };
-                                if ab.is_some() && bb.is_some() {
-                                    return ab.unwrap().partial_cmp(&bb.unwrap()).unwrap();
-                                }
-
-                                let a = match a {
-                                    Ast::Literal(Literal {
-                                        content: TokenContentType::String(abs),
-                                    }) => Some(abs),
-                                    _ => None,
-                                };
-                                let b = match b {
-                                    Ast::Literal(Literal {
-                                        content: TokenContentType::String(b),
-                                    }) => Some(b),
-                                    _ => None,
-                                };
-                                if a.is_some() && b.is_some() {
-                                    return a.partial_cmp(&b).unwrap();
-                                }
-                                panic!("Expected number or string as array elements!")
-                            });
-                            Ast::Array(array)
-                        }
-                        _ => panic!("Expected array as first argument"),
-                    };
+            // arrays and instances are shared, so these are the way to get a separate one
+            scope.borrow_mut().declare(
+                "copy".to_string(),
+                Value::native("copy", |args| match &args[..] {
+                    [value] => Ok(value.copy()),
+                    _ => Err(badArguments("copy", "1 argument", &args)),
                 }),
             );
 
-            Interpreter::run(ast, scope, standardLibraryFunctions, structScope);
+            scope.borrow_mut().declare(
+                "deepCopy".to_string(),
+                Value::native("deepCopy", |args| match &args[..] {
+                    [value] => Ok(value.deepCopy()),
+                    _ => Err(badArguments("deepCopy", "1 argument", &args)),
+                }),
+            );
+
+            // the dialect's names for builtins, as ordinary globals the program can shadow
+            for (alias, builtin) in dialect.aliases() {
+                let value = scope.borrow().get(builtin);
+                if let Some(value) = value {
+                    scope.borrow_mut().declare(alias.to_string(), value);
+                }
+            }
+
+            if let Err(error) = Interpreter::run(&ast, scope, structScope) {
+                eprintln!("{}", error);
+                if let Some(span) = error.span.as_ref().filter(|span| *span.file == **location) {
+                    eprintln!("{}", underline(&program, span));
+                }
+                for line in error.backtrace() {
+                    eprintln!("    {}", line);
+                }
+                std::process::exit(1);
+            }
         }
         None => {
             // No file provided, go to REPL?
             println!("Usage: {} <file>", argv[0]);
-            return;
         }
     }
 }
@@ -1,14 +1,64 @@
 use crate::ast::Array;
 use crate::ast::Ast;
 use crate::ast::Literal;
+use crate::ast::Param;
+use crate::lexer::Span;
+use crate::lexer::StringPart;
 use crate::lexer::Token;
+use crate::lexer::TokenContentType;
 use crate::lexer::TokenType;
 use std::collections::HashMap;
 
+// keywords that can only start a statement, so the parser can pick up again at one after an error
+const STATEMENT_KEYWORDS: [&str; 9] = [
+    "let", "struct", "func", "finished", "loop", "while", "if", "break", "continue",
+];
+
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub expected: String,
+    pub found: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(expected: impl Into<String>, token: &Token) -> ParseError {
+        let found = match token._type {
+            TokenType::EOF => "end of file".to_string(),
+            TokenType::String | TokenType::Interpolation => "string".to_string(),
+            TokenType::Identifier => format!("identifier `{}`", token.value),
+            _ => format!("`{}`", token.value),
+        };
+        ParseError {
+            expected: expected.into(),
+            found,
+            span: token.span.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: Expected {}, found {}",
+            self.span, self.expected, self.found
+        )
+    }
+}
+
+// the positional and `name: value` arguments of a call
+type Arguments = (Vec<Ast>, Vec<(String, Ast)>);
+
 pub struct Parser {
     tokens: Vec<Token>,
     ast: Vec<Ast>,
+    errors: Vec<ParseError>,
     current: usize,
+    // labels of the loops around the statement being parsed, innermost last
+    loops: Vec<Option<String>>,
+    // how many blocks deep the statement being parsed is
+    blocks: usize,
 }
 
 impl Parser {
@@ -16,478 +66,771 @@ impl Parser {
         Parser {
             tokens,
             ast: vec![],
+            errors: vec![],
             current: 0,
+            loops: vec![],
+            blocks: 0,
         }
     }
 
-    fn peek(&self) -> Option<Token> {
-        if self.current >= self.tokens.len() {
-            return None;
-        }
-        Some(self.tokens[self.current].clone())
+    // the token stream always ends in EOF, so peeking past the end keeps returning it
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current.min(self.tokens.len() - 1)]
     }
 
-    fn peekType(&self) -> Option<TokenType> {
-        if self.current >= self.tokens.len() {
-            return None;
-        }
-        Some(self.tokens[self.current]._type)
+    fn peekType(&self) -> TokenType {
+        self.peek()._type
     }
 
-    pub fn parse(&mut self) -> Vec<Ast> {
-        while let Some(nextType) = self.peekType() {
-            // If we reach the end of the file, break
-            if matches!(nextType, TokenType::EOF) {
-                break;
-            }
+    fn peekTypeAt(&self, offset: usize) -> TokenType {
+        self.tokens[(self.current + offset).min(self.tokens.len() - 1)]._type
+    }
 
-            let stmt = self.stmt();
-            self.ast.push(stmt);
+    /**
+     * Parses the whole program. A statement that fails to parse is reported and skipped, so the
+     * returned errors cover the entire file rather than stopping at the first mistake.
+     */
+    pub fn parse(&mut self) -> (Vec<Ast>, Vec<ParseError>) {
+        while !matches!(self.peekType(), TokenType::EOF) {
+            if let Some(stmt) = self.recoverableStmt() {
+                self.ast.push(stmt);
+            }
         }
 
-        self.ast.clone()
+        (self.ast.clone(), self.errors.clone())
     }
 
-    pub fn eat(&mut self, token_type: TokenType) -> Token {
-        // println!(
-        // "self.current in eat: {:?} with expected type: {:?}",
-        // self.current,
-        // token_type
-        // );
-        match self.peekType() {
-            Some(tokenType2) => {
-                if tokenType2.to_string() != token_type.to_string() {
-                    panic!(
-                        "Expected token type {:?}, got {:?}",
-                        token_type,
-                        self.peek().unwrap()
-                    );
-                }
-                self.current += 1;
-                return self.tokens[self.current - 1].clone();
-            }
-            None => {
-                panic!("Expected token type {:?}, got None", token_type);
-            }
+    pub fn eat(&mut self, token_type: TokenType) -> Result<Token, ParseError> {
+        if self.peekType() != token_type {
+            return Err(ParseError::new(token_type.describe(), self.peek()));
         }
+        self.current += 1;
+        Ok(self.tokens[self.current - 1].clone())
     }
 
-    fn identifierList(&mut self) -> Vec<String> {
+    fn identifierList(&mut self) -> Result<Vec<String>, ParseError> {
         let mut identifiers = vec![];
-        identifiers.push(self.eat(TokenType::Identifier).value);
+        identifiers.push(self.eat(TokenType::Identifier)?.value);
         // println!("identifiers: {:?}", identifiers);
+        while matches!(self.peekType(), TokenType::Comma) {
+            self.eat(TokenType::Comma)?;
+            identifiers.push(self.eat(TokenType::Identifier)?.value);
+        }
+        Ok(identifiers)
+    }
+
+    // `name` or `name = default`, separated by commas, optionally ending in a `...rest`
+    fn paramList(&mut self) -> Result<Vec<Param>, ParseError> {
+        let mut params = vec![self.param()?];
+        while matches!(self.peekType(), TokenType::Comma) {
+            if params.last().is_some_and(|param| param.rest) {
+                return Err(ParseError::new("`)` after the rest parameter", self.peek()));
+            }
+            self.eat(TokenType::Comma)?;
+            params.push(self.param()?);
+        }
+        Ok(params)
+    }
+
+    fn param(&mut self) -> Result<Param, ParseError> {
+        if self.peekType() == TokenType::Ellipsis {
+            self.eat(TokenType::Ellipsis)?;
+            let mut param = Param::from(self.eat(TokenType::Identifier)?.value);
+            param.rest = true;
+            return Ok(param);
+        }
+        let mut param = Param::from(self.eat(TokenType::Identifier)?.value);
+        if self.peekKeyword("=").is_some() {
+            self.eatKeyword("=")?;
+            param.default = Some(self.expr()?);
+        }
+        Ok(param)
+    }
+
+    /**
+     * Call arguments: expressions, then any `name: value` pairs. Positional ones can't follow
+     * named ones, since there'd be no telling which parameter they were meant for.
+     */
+    fn arguments(&mut self) -> Result<Arguments, ParseError> {
+        let mut args = vec![];
+        let mut named = vec![];
         loop {
-            let next = self.peek();
-            match next {
-                Some(token) => match token._type {
-                    TokenType::Comma => {
-                        self.eat(TokenType::Comma);
-                        identifiers.push(self.eat(TokenType::Identifier).value);
-                    }
-                    _ => {
-                        break;
-                    }
-                },
-                None => {
-                    break;
-                }
+            if self.peekType() == TokenType::Identifier && self.peekTypeAt(1) == TokenType::Colon {
+                let name = self.eat(TokenType::Identifier)?.value;
+                self.eat(TokenType::Colon)?;
+                named.push((name, self.expr()?));
+            } else if named.is_empty() {
+                args.push(self.element()?);
+            } else {
+                return Err(ParseError::new("named argument", self.peek()));
+            }
+
+            if !matches!(self.peekType(), TokenType::Comma) {
+                break;
             }
+            self.eat(TokenType::Comma)?;
         }
-        identifiers
+        Ok((args, named))
     }
 
-    pub fn simple(&mut self) -> Ast {
-        // println!("token: {:?}", self.peek().unwrap());
-        let token = self.eat(self.peekType().unwrap());
+    pub fn simple(&mut self) -> Result<Ast, ParseError> {
+        // println!("token: {:?}", self.peek());
+        let token = self.peek().clone();
+        self.current += 1;
         match token._type {
             TokenType::String | TokenType::Number | TokenType::Boolean => {
-                return Ast::Literal(Literal::from(token.content.clone()));
+                Ok(Ast::Literal(Literal::from(token.content.clone())))
             }
             TokenType::LeftBracket => {
                 let mut items = Vec::new();
-                let nextType = self.peekType();
-                // println!("nextType in simple: {:?}", nextType);
-                if !nextType.is_some_and(|x| matches!(x, TokenType::RightBracket)) {
-                    items = self.exprList();
+                // println!("nextType in simple: {:?}", self.peekType());
+                if !matches!(self.peekType(), TokenType::RightBracket) {
+                    items = self.elementList()?;
                 }
-                self.eat(TokenType::RightBracket);
-                return Ast::Array(Array::from(items));
+                self.eat(TokenType::RightBracket)?;
+                Ok(Ast::Array(Array::from(items)))
             }
-            TokenType::Identifier => return Ast::Var(token.value.clone(), None),
+            TokenType::Interpolation => {
+                let TokenContentType::Interpolation(parts) = token.content else {
+                    unreachable!("interpolation tokens always carry their parts");
+                };
+                let parts = parts
+                    .into_iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => Ok(Ast::Literal(Literal::from(text.into()))),
+                        StringPart::Code(tokens) => {
+                            let mut parser = Parser::new(tokens);
+                            let expr = parser.expr()?;
+                            parser.eat(TokenType::EOF)?;
+                            // errors the code recovered from, which would otherwise go with its parser
+                            self.errors.extend(parser.errors);
+                            Ok(expr)
+                        }
+                    })
+                    .collect::<Result<Vec<Ast>, ParseError>>()?;
+                Ok(Ast::Interpolation(parts))
+            }
+            TokenType::Identifier => Ok(Ast::Var(token.value.clone(), None, token.span.clone())),
             TokenType::LeftParen => {
-                let expr = self.expr();
-                // println!("RIGHTPAREN 1");
-                self.eat(TokenType::RightParen);
-                return expr;
+                let expr = self.expr()?;
+                self.eat(TokenType::RightParen)?;
+                Ok(expr)
             }
-            TokenType::Keyword => match token.value.as_str() {
-                "prep" => {
-                    let id = self.eat(TokenType::Identifier).value.clone();
-                    // println!("id: {:?}", id);
-
-                    self.eat(TokenType::LeftParen);
-
-                    let mut members: HashMap<String, Ast> = HashMap::new();
-                    while !matches!(self.peekType().unwrap(), TokenType::RightParen) {
-                        let member = self.eat(TokenType::Identifier).value.clone();
-                        self.eat(TokenType::Colon);
-                        members.insert(member, self.expr());
-                        if matches!(self.peekType().unwrap(), TokenType::Comma) {
-                            self.eat(TokenType::Comma);
-                        }
+            TokenType::Keyword if token.value == "func" => {
+                let mut params = vec![];
+                if matches!(self.peekType(), TokenType::LeftParen) {
+                    self.eat(TokenType::LeftParen)?;
+                    if !matches!(self.peekType(), TokenType::RightParen) {
+                        params = self.paramList()?;
                     }
-
-                    // println!("RIGHTPAREN 2");
-                    self.eat(TokenType::RightParen);
-
-                    return Ast::Instance(id, members);
+                    self.eat(TokenType::RightParen)?;
                 }
-                _ => {
-                    panic!("Unexpected keyword: {:?}", token.value);
+                let body = self.functionBody()?;
+                Ok(Ast::Lambda(params, body))
+            }
+            TokenType::Keyword if token.value == "prep" => {
+                let idToken = self.eat(TokenType::Identifier)?;
+                let id = idToken.value.clone();
+                // println!("id: {:?}", id);
+
+                self.eat(TokenType::LeftParen)?;
+
+                let mut members: HashMap<String, Ast> = HashMap::new();
+                while !matches!(self.peekType(), TokenType::RightParen) {
+                    let member = self.eat(TokenType::Identifier)?.value.clone();
+                    self.eat(TokenType::Colon)?;
+                    members.insert(member, self.expr()?);
+                    if matches!(self.peekType(), TokenType::Comma) {
+                        self.eat(TokenType::Comma)?;
+                    } else {
+                        break;
+                    }
                 }
-            },
+
+                self.eat(TokenType::RightParen)?;
+
+                Ok(Ast::Instance(id, members, idToken.span))
+            }
             _ => {
-                panic!("Unexpected token: {:?}", token);
+                // leave the token where it was, it may well start the next statement
+                self.current -= 1;
+                Err(ParseError::new("expression", &token))
             }
         }
     }
 
-    fn call(&mut self) -> Ast {
-        let mut expr = self.simple();
+    fn call(&mut self) -> Result<Ast, ParseError> {
+        let start = self.peek().span.clone();
+        let mut expr = self.simple()?;
         // println!("expr: {:?}", expr);
         loop {
-            // println!("self.peekType().unwrap(): {:?}", self.peekType().unwrap());
-            // println!("self.peek().unwrap(): {:?}", self.peek().unwrap());
-            match self.peekType().unwrap() {
+            match self.peekType() {
                 TokenType::LeftParen => {
-                    self.eat(TokenType::LeftParen);
+                    self.eat(TokenType::LeftParen)?;
 
-                    let mut args = vec![];
-                    if !matches!(self.peekType().unwrap(), TokenType::RightParen) {
-                        args = self.exprList();
+                    let (mut args, mut named) = (vec![], vec![]);
+                    if !matches!(self.peekType(), TokenType::RightParen) {
+                        (args, named) = self.arguments()?;
                         // println!("args: {:?}", args);
-                    } else {
-                        // println!("no arguments to function call: {:?}", expr);
                     }
 
-                    // println!("RIGHTPAREN 3");
-                    // println!(
-                    // "eaten right parens in call: {:?}",
-                    self.eat(TokenType::RightParen);
-                    // );
-                    // println!("self.current in call: {:?}", self.current);
-                    // println!("eaten right parens in call: {:?}", expr);
-                    expr = Ast::Call(Box::new(expr), args);
+                    let end = self.eat(TokenType::RightParen)?.span;
+                    expr = Ast::Call(Box::new(expr), args, named, start.to(&end));
 
                     // println!("expr: {:?}", expr);
                 }
                 TokenType::LeftBracket => {
-                    self.eat(TokenType::LeftBracket);
-                    let property = self.expr();
+                    self.eat(TokenType::LeftBracket)?;
+                    let property = self.expr()?;
                     // println!("property: {:?}", property);
-                    self.eat(TokenType::RightBracket);
-                    expr = Ast::Get(Box::new(expr), Box::new(property), true);
+                    let end = self.eat(TokenType::RightBracket)?.span;
+                    expr = Ast::Get(Box::new(expr), Box::new(property), true, start.to(&end));
                 }
                 TokenType::Period => {
-                    self.eat(TokenType::Period);
-                    let property = self.eat(TokenType::Identifier).value.clone();
+                    self.eat(TokenType::Period)?;
+                    let property = self.eat(TokenType::Identifier)?;
                     expr = Ast::Get(
                         Box::new(expr),
                         Box::new(Ast::Literal(Literal {
-                            content: property.into(),
+                            content: property.value.into(),
                         })),
                         false,
+                        start.to(&property.span),
                     );
                 }
                 _ => break,
             }
         }
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Ast {
-        match self.peekType().unwrap() {
+    fn unary(&mut self) -> Result<Ast, ParseError> {
+        match self.peekType() {
             TokenType::Not => {
-                let op = self.eat(self.peekType().unwrap()).value;
-                Ast::Unary(TokenType::Not, Box::new(self.unary()))
+                let span = self.eat(TokenType::Not)?.span;
+                Ok(Ast::Unary(TokenType::Not, Box::new(self.unary()?), span))
+            }
+            TokenType::Minus | TokenType::Plus => {
+                let Token {
+                    _type: op, span, ..
+                } = self.eat(self.peekType())?;
+                match (op, self.unary()?) {
+                    // `-5` is just a negative number
+                    (
+                        TokenType::Minus,
+                        Ast::Literal(Literal {
+                            content: TokenContentType::Number(n),
+                        }),
+                    ) => Ok(Ast::Literal(Literal::from((-n).into()))),
+                    (op, operand) => Ok(Ast::Unary(op, Box::new(operand), span)),
+                }
             }
             _ => self.call(),
         }
     }
 
-    pub fn expr(&mut self) -> Ast {
-        let left = self.unary();
+    pub fn expr(&mut self) -> Result<Ast, ParseError> {
+        self.binary(0)
+    }
+
+    /**
+     * Precedence climbing: parses a run of binary operators that bind at least as tightly as
+     * `minPrecedence`. Operands come from `unary`, so prefix operators bind tighter than any
+     * binary one.
+     */
+    fn binary(&mut self, minPrecedence: i32) -> Result<Ast, ParseError> {
+        let mut left = self.unary()?;
         // println!("left: {:?}", left);
-        if self.peekType().unwrap().isOperator() {
-            let op = self.eat(self.peekType().unwrap())._type;
-            let right = self.expr();
-            match right.clone() {
-                Ast::Binary(rightLeft, rightOp, rightRight) => {
-                    if op.precedence() > rightOp.precedence() {
-                        return Ast::Binary(
-                            Box::new(Ast::Binary(Box::new(left), op, rightLeft)),
-                            rightOp,
-                            rightRight,
-                        );
-                    }
-                    return Ast::Binary(Box::new(left), op, Box::new(right));
-                }
-                _ => {}
+        loop {
+            let op = self.peekType();
+            if !op.isOperator() || op.precedence() < minPrecedence {
+                break;
             }
-            return Ast::Binary(Box::new(left), op, Box::new(right));
+            let span = self.eat(op)?.span;
+
+            // the right operand only takes operators that bind tighter, unless `op` groups right
+            let nextPrecedence = if op.isRightAssociative() {
+                op.precedence()
+            } else {
+                op.precedence() + 1
+            };
+            let right = self.binary(nextPrecedence)?;
+            left = Ast::Binary(Box::new(left), op, Box::new(right), span);
         }
-        left
+        Ok(left)
     }
 
-    pub fn exprList(&mut self) -> Vec<Ast> {
+    pub fn elementList(&mut self) -> Result<Vec<Ast>, ParseError> {
         let mut exprs = vec![];
-        exprs.push(self.expr());
-        loop {
-            let next = self.peek();
-            match next {
-                Some(token) => match token._type {
-                    TokenType::Comma => {
-                        self.eat(TokenType::Comma);
-                        exprs.push(self.expr());
-                    }
-                    _ => {
-                        break;
-                    }
-                },
-                None => {
-                    break;
-                }
-            }
+        exprs.push(self.element()?);
+        while matches!(self.peekType(), TokenType::Comma) {
+            self.eat(TokenType::Comma)?;
+            exprs.push(self.element()?);
+        }
+        Ok(exprs)
+    }
+
+    // an array item or positional argument: an expression, or `...array` to spread one in
+    fn element(&mut self) -> Result<Ast, ParseError> {
+        if self.peekType() == TokenType::Ellipsis {
+            let span = self.eat(TokenType::Ellipsis)?.span;
+            return Ok(Ast::Spread(Box::new(self.expr()?), span));
         }
-        exprs
+        self.expr()
     }
 
     fn peekKeyword(&self, keyword: &'static str) -> Option<Token> {
-        let nextType = self.peekType().unwrap();
-        match nextType {
-            TokenType::Keyword => {
-                if self.peek().unwrap().value == keyword {
-                    return Some(self.peek().unwrap());
-                }
-            }
-            _ => {}
+        let next = self.peek();
+        if next._type == TokenType::Keyword && next.value == keyword {
+            return Some(next.clone());
         }
         None
     }
 
-    fn eatKeyword(&mut self, keyword: &'static str) -> Token {
-        let next = self.peekKeyword(keyword);
-        match next {
-            Some(token) => {
-                if token.value != keyword {
-                    panic!("Expected keyword {:?}, got {:?}", keyword, token.value);
-                }
-                self.eat(TokenType::Keyword)
+    fn eatKeyword(&mut self, keyword: &'static str) -> Result<Token, ParseError> {
+        match self.peekKeyword(keyword) {
+            Some(_) => self.eat(TokenType::Keyword),
+            None => Err(ParseError::new(format!("`{}`", keyword), self.peek())),
+        }
+    }
+
+    /**
+     * A braced list of statements. Bad statements inside are reported and skipped here, so the
+     * rest of the block (and whatever follows it) still gets parsed.
+     */
+    fn block(&mut self) -> Result<Vec<Ast>, ParseError> {
+        self.eat(TokenType::LeftBrace)?;
+        self.blocks += 1;
+        let mut body = vec![];
+        while !matches!(self.peekType(), TokenType::RightBrace | TokenType::EOF) {
+            if let Some(stmt) = self.recoverableStmt() {
+                body.push(stmt);
             }
-            None => {
-                panic!("Expected keyword {:?}, got None", keyword);
+        }
+        self.blocks -= 1;
+        self.eat(TokenType::RightBrace)?;
+        Ok(body)
+    }
+
+    // a statement, or None after recording its error and skipping to where the next one starts
+    fn recoverableStmt(&mut self) -> Option<Ast> {
+        let start = self.current;
+        match self.stmt() {
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize(start);
+                None
             }
         }
     }
 
-    fn funcStmt(&mut self) -> Ast {
-        self.eatKeyword("func");
-        let name = self.eat(TokenType::Identifier).value;
+    /**
+     * Skips tokens up to the next statement keyword or the brace closing the current block.
+     * Braces opened along the way are skipped as a whole, so a bad `if (...) { ... }` doesn't end
+     * the block around it early.
+     */
+    fn synchronize(&mut self, start: usize) {
+        // always make progress, or a statement that fails on its first token would loop forever
+        if self.current == start {
+            self.current += 1;
+        }
+
+        let mut depth = 0;
+        loop {
+            let next = self.peek();
+            match next._type {
+                TokenType::EOF => return,
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth > 0 => depth -= 1,
+                // outside every block a `}` closes nothing, so it's skipped like any other token
+                TokenType::RightBrace if self.blocks > 0 => return,
+                TokenType::Keyword
+                    if depth == 0 && STATEMENT_KEYWORDS.contains(&next.value.as_str()) =>
+                {
+                    return
+                }
+                _ => {}
+            }
+            self.current += 1;
+        }
+    }
+
+    fn funcStmt(&mut self) -> Result<Ast, ParseError> {
+        let doc = self.eatKeyword("func")?.doc;
+        let name = self.eat(TokenType::Identifier)?.value;
 
         let mut params = vec![];
-        if self
-            .peekKeyword("needs")
-            .is_some_and(|x| x.value == "needs")
-        {
-            self.eatKeyword("needs");
-            self.eat(TokenType::LeftParen);
-            params = self.identifierList();
+        if self.peekKeyword("needs").is_some() {
+            self.eatKeyword("needs")?;
+            self.eat(TokenType::LeftParen)?;
+            params = self.paramList()?;
             // println!("params: {:?}", params);
-            // println!("RIGHTPAREN 4");
-            self.eat(TokenType::RightParen);
+            self.eat(TokenType::RightParen)?;
         }
 
-        self.eat(TokenType::LeftBrace);
-        let mut body = vec![];
-        while !matches!(self.peekType().unwrap(), TokenType::RightBrace) {
-            body.push(self.stmt());
-        }
-        self.eat(TokenType::RightBrace);
+        let body = self.functionBody()?;
 
-        Ast::Func(name, params, body)
+        Ok(Ast::Func(name, params, body, doc))
     }
 
-    fn returnStmt(&mut self) -> Ast {
-        self.eatKeyword("finished");
-        let expr = self.expr();
-        Ast::Return(Box::new(expr))
+    fn functionBody(&mut self) -> Result<Vec<Ast>, ParseError> {
+        // loops around the function don't count inside it, `break` can't leave a function
+        let outerLoops = std::mem::take(&mut self.loops);
+        let body = self.block();
+        self.loops = outerLoops;
+        body
     }
 
-    fn forStmt(&mut self) -> Ast {
-        self.eatKeyword("loop");
-        let id = self.eat(TokenType::Identifier).value;
-        self.eatKeyword("through");
+    fn returnStmt(&mut self) -> Result<Ast, ParseError> {
+        self.eatKeyword("finished")?;
+        let expr = self.expr()?;
+        Ok(Ast::Return(Box::new(expr)))
+    }
+
+    // a loop body, during which `break` and `continue` are allowed
+    fn loopBody(&mut self, label: Option<String>) -> Result<Vec<Ast>, ParseError> {
+        self.loops.push(label);
+        let body = self.block();
+        self.loops.pop();
+        body
+    }
 
-        self.eat(TokenType::LeftParen);
-        let range = self.exprList();
-        if range.len() != 2 {
-            panic!("Expected range to have 2 elements, got {:?}", range.len());
+    // `break` or `continue`, with an optional label on the same line naming an enclosing loop
+    fn jumpStmt(&mut self, keyword: &'static str) -> Result<Ast, ParseError> {
+        let token = self.eatKeyword(keyword)?;
+        if self.loops.is_empty() {
+            return Err(ParseError {
+                expected: format!("a loop around `{}`", keyword),
+                found: format!("`{}` outside of any loop", keyword),
+                span: token.span,
+            });
         }
-        // println!("RIGHTPAREN 5");
-        self.eat(TokenType::RightParen);
 
-        self.eat(TokenType::LeftBrace);
-        let mut body = vec![];
-        while !matches!(self.peekType().unwrap(), TokenType::RightBrace) {
-            body.push(self.stmt());
+        let mut label = None;
+        let next = self.peek().clone();
+        if next._type == TokenType::Identifier && next.span.start_line == token.span.end_line {
+            if !self.loops.contains(&Some(next.value.clone())) {
+                return Err(ParseError::new("label of an enclosing loop", &next));
+            }
+            self.current += 1;
+            label = Some(next.value);
         }
-        self.eat(TokenType::RightBrace);
 
-        Ast::For(id, range, body)
+        match keyword {
+            "break" => Ok(Ast::Break(label)),
+            _ => Ok(Ast::Continue(label)),
+        }
     }
 
-    fn whileStmt(&mut self) -> Ast {
-        self.eatKeyword("while");
+    fn forStmt(&mut self, label: Option<String>) -> Result<Ast, ParseError> {
+        let keyword = self.eatKeyword("loop")?;
+        let id = self.eat(TokenType::Identifier)?.value;
+        self.eatKeyword("through")?;
 
-        self.eat(TokenType::LeftParen);
-        let condition = self.expr();
-        // println!("RIGHTPAREN 6");
-        self.eat(TokenType::RightParen);
+        self.eat(TokenType::LeftParen)?;
+        let start = self.expr()?;
+        self.eat(TokenType::Comma)?;
+        let end = self.expr()?;
+        self.eat(TokenType::RightParen)?;
 
-        self.eat(TokenType::LeftBrace);
-        let mut body = vec![];
-        while !matches!(self.peekType().unwrap(), TokenType::RightBrace) {
-            body.push(self.stmt());
-        }
-        self.eat(TokenType::RightBrace);
+        let body = self.loopBody(label.clone())?;
+
+        Ok(Ast::For(id, vec![start, end], body, label, keyword.span))
+    }
+
+    fn whileStmt(&mut self, label: Option<String>) -> Result<Ast, ParseError> {
+        let keyword = self.eatKeyword("while")?;
+
+        self.eat(TokenType::LeftParen)?;
+        let condition = self.expr()?;
+        self.eat(TokenType::RightParen)?;
+
+        let body = self.loopBody(label.clone())?;
 
-        return Ast::While(Box::new(condition), body);
+        Ok(Ast::While(Box::new(condition), body, label, keyword.span))
     }
 
     // todo: you could totally avoid the nonsense for the conditionalStmt
     // if you just used separate if statements for the otherwise and elif
-    fn conditionalStmt(&mut self, keyword: &'static str) -> Ast {
-        self.eatKeyword(keyword);
+    fn conditionalStmt(&mut self, keyword: &'static str) -> Result<Ast, ParseError> {
+        self.eatKeyword(keyword)?;
 
         let mut condition = Ast::Literal(Literal::from(true.into()));
         if keyword != "else" {
-            self.eat(TokenType::LeftParen);
-            condition = self.expr();
-            // println!("RIGHTPAREN 7");
-            // println!("self.current in conditionalStmt: {:?}", self.current);
-            self.eat(TokenType::RightParen);
+            self.eat(TokenType::LeftParen)?;
+            condition = self.expr()?;
+            self.eat(TokenType::RightParen)?;
         }
 
-        // println!("self.current1 in conditionalStmt: {:?}", self.current);
-        self.eat(TokenType::LeftBrace);
-        // println!("self.current2 in conditionalStmt: {:?}", self.current);
-        let mut body = vec![];
-        // println!("starting conditional body: {:?}", body);
-        while !matches!(self.peekType().unwrap(), TokenType::RightBrace) {
-            // println!("peekType: {:?}", self.peekType().unwrap());
-            // println!("self.current3 in conditionalStmt: {:?}", self.current);
-            body.push(self.stmt());
-            // println!("self.current4 in conditionalStmt: {:?}", self.current);
-            // println!("conditional body: {:?}", body)
-        }
-        // println!("end of conditional body: {:?}", body);
-        // println!(
-        // "eaten right brace in conditionalStmt: {:?}",
-        self.eat(TokenType::RightBrace);
-        // );
+        let body = self.block()?;
 
         let mut otherwise = vec![];
         loop {
-            let elseKeyword = self.peekKeyword("else");
-            let elifKeyword = self.peekKeyword("elif");
-
-            if elseKeyword.is_some() {
-                otherwise.push(self.conditionalStmt("else"));
-            } else if elifKeyword.is_some() {
-                otherwise.push(self.conditionalStmt("elif"));
+            if self.peekKeyword("else").is_some() {
+                otherwise.push(self.conditionalStmt("else")?);
+            } else if self.peekKeyword("elif").is_some() {
+                otherwise.push(self.conditionalStmt("elif")?);
             } else {
                 break;
             }
         }
 
-        Ast::Conditional(Box::new(condition), body, otherwise)
+        Ok(Ast::Conditional(Box::new(condition), body, otherwise))
     }
 
-    fn assignStmt(&mut self) -> Ast {
-        self.eatKeyword("let");
-        let name = self.eat(TokenType::Identifier).value;
+    fn assignStmt(&mut self) -> Result<Ast, ParseError> {
+        self.eatKeyword("let")?;
+        let start = self.peek().clone();
+        let target = self.call()?;
+        if !Parser::isAssignable(&target) {
+            return Err(ParseError::new("variable, field or array element", &start));
+        }
+
+        if self.peekType().compoundOperator().is_some() {
+            return self.compoundAssign(target);
+        }
 
-        if matches!(self.peekType().unwrap(), TokenType::Period) {
-            self.eat(TokenType::Period);
-            let property = self.eat(TokenType::Identifier).value;
-            self.eatKeyword("=");
-            let value = self.expr();
-            return Ast::Set(name, property, Box::new(value.clone()));
-        } else if matches!(self.peekType().unwrap(), TokenType::LeftBracket) {
-            self.eat(TokenType::LeftBracket);
-            let index = self.expr();
-            self.eat(TokenType::RightBracket);
-            self.eatKeyword("=");
-            let value = self.expr();
-            return Ast::Set(name, index.into(), Box::new(value.clone()));
+        self.eatKeyword("=")?;
+        let value = self.expr()?;
+        match target {
+            Ast::Var(name, None, span) => Ok(Ast::Var(name, Some(Box::new(value)), span)),
+            _ => Ok(Ast::Assign(Box::new(target), Box::new(value))),
         }
+    }
 
-        self.eatKeyword("=");
-        let value = self.expr();
-        Ast::Var(name, Some(Box::new(value)))
+    // `target += value` and friends, with or without a leading `let`
+    fn compoundAssign(&mut self, target: Ast) -> Result<Ast, ParseError> {
+        let token = self.peek().clone();
+        let Some(op) = token._type.compoundOperator() else {
+            return Err(ParseError::new("`+=`, `-=`, `*=`, `/=` or `%=`", &token));
+        };
+        self.current += 1;
+        let value = self.expr()?;
+        Ok(Ast::CompoundAssign(Box::new(target), op, Box::new(value)))
     }
 
-    fn structStmt(&mut self) -> Ast {
-        self.eatKeyword("struct");
-        let name = self.eat(TokenType::Identifier).value;
-        self.eatKeyword("has"); // todo: remove this or change it
+    // a variable, or any chain of `.field` and `[index]` on one, e.g. `cells[i].live`
+    fn isAssignable(target: &Ast) -> bool {
+        match target {
+            Ast::Var(_, None, _) => true,
+            Ast::Get(caller, _, _, _) => Parser::isAssignable(caller),
+            _ => false,
+        }
+    }
 
-        self.eat(TokenType::LeftBrace);
-        let members = self.identifierList();
-        self.eat(TokenType::RightBrace);
+    fn structStmt(&mut self) -> Result<Ast, ParseError> {
+        let doc = self.eatKeyword("struct")?.doc;
+        let name = self.eat(TokenType::Identifier)?.value;
+        self.eatKeyword("has")?; // todo: remove this or change it
 
-        Ast::Struct(name, members)
+        self.eat(TokenType::LeftBrace)?;
+        let members = self.identifierList()?;
+        self.eat(TokenType::RightBrace)?;
+
+        Ok(Ast::Struct(name, members, doc))
     }
 
-    pub fn stmt(&mut self) -> Ast {
+    pub fn stmt(&mut self) -> Result<Ast, ParseError> {
         let next = self.peek();
         // println!("next token in stmt(): {:?}", next);
-        match next {
-            Some(token) => match token._type {
-                TokenType::Keyword => match token.value.as_str() {
-                    "func" => {
-                        return self.funcStmt();
-                    }
-                    "finished" => {
-                        return self.returnStmt();
-                    }
-                    "loop" => {
-                        return self.forStmt();
-                    }
-                    "while" => {
-                        return self.whileStmt();
-                    }
-                    "if" => {
-                        return self.conditionalStmt("if");
-                    }
-                    "let" => {
-                        return self.assignStmt();
-                    }
-                    "struct" => {
-                        return self.structStmt();
-                    }
-                    _ => {
-                        panic!("Unexpected keyword: {:?}", token.value);
-                    }
-                },
-                _ => {
-                    return self.expr();
-                    // panic!("Unexpected token: {:?}", token);
-                }
+        match next._type {
+            TokenType::Keyword => match next.value.as_str() {
+                // `func (x) { ... }` on its own is a lambda expression
+                "func" if self.peekTypeAt(1) == TokenType::Identifier => self.funcStmt(),
+                "finished" => self.returnStmt(),
+                "loop" => self.forStmt(None),
+                "while" => self.whileStmt(None),
+                "break" => self.jumpStmt("break"),
+                "continue" => self.jumpStmt("continue"),
+                "if" => self.conditionalStmt("if"),
+                "let" => self.assignStmt(),
+                "struct" => self.structStmt(),
+                _ => self.expr(),
             },
-            None => {
-                panic!("Unexpected EOF");
+            // `outer: loop ...`
+            TokenType::Identifier if self.peekTypeAt(1) == TokenType::Colon => {
+                let label = self.eat(TokenType::Identifier)?.value;
+                self.eat(TokenType::Colon)?;
+                if self.peekKeyword("loop").is_some() {
+                    self.forStmt(Some(label))
+                } else if self.peekKeyword("while").is_some() {
+                    self.whileStmt(Some(label))
+                } else {
+                    Err(ParseError::new(
+                        "`loop` or `while` after a label",
+                        self.peek(),
+                    ))
+                }
+            }
+            _ => {
+                let start = self.peek().clone();
+                let expr = self.expr()?;
+                let isAssignment = self.peekKeyword("=").is_some();
+                if !isAssignment && self.peekType().compoundOperator().is_none() {
+                    return Ok(expr);
+                }
+                if !Parser::isAssignable(&expr) {
+                    return Err(ParseError::new("variable, field or array element", &start));
+                }
+                if !isAssignment {
+                    return self.compoundAssign(expr);
+                }
+
+                // `x = v` without `let` updates an existing variable instead of declaring one
+                self.eatKeyword("=")?;
+                let value = self.expr()?;
+                Ok(Ast::Assign(Box::new(expr), Box::new(value)))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(program: &str) -> (Vec<Ast>, Vec<ParseError>) {
+        let tokens = Lexer::new(program.to_string(), "test.txt")
+            .scan_tokens()
+            .unwrap();
+        Parser::new(tokens).parse()
+    }
+
+    fn messages(errors: &[ParseError]) -> Vec<String> {
+        errors.iter().map(ParseError::to_string).collect()
+    }
+
+    #[test]
+    fn reportsEveryBadStatement() {
+        let (ast, errors) = parse(
+            "let a = 1\nlet b = * 2\nfunc f needs (x) {\n    let y = )\n    finished x\n}\nprint(a\nlet c = 3\nlet d = }\n",
+        );
+        assert_eq!(
+            messages(&errors),
+            vec![
+                "test.txt:2:9: Expected expression, found `*`",
+                "test.txt:4:13: Expected expression, found `)`",
+                "test.txt:8:1: Expected `)`, found `let`",
+                "test.txt:9:9: Expected expression, found `}`",
+            ]
+        );
+
+        // the good statements around them, and the rest of the block with the bad one, survive
+        assert_eq!(ast.len(), 3);
+        assert!(matches!(&ast[0], Ast::Var(name, Some(_), _) if name == "a"));
+        let Ast::Func(name, _, body, _) = &ast[1] else {
+            panic!("expected the function, got {:?}", ast[1]);
+        };
+        assert_eq!(name, "f");
+        assert!(matches!(&body[..], [Ast::Return(_)]));
+        assert!(matches!(&ast[2], Ast::Var(name, Some(_), _) if name == "c"));
+    }
+
+    #[test]
+    fn badBlockDoesNotEndTheOneAroundIt() {
+        let (ast, errors) = parse(
+            "while (true) {\n    if (1 +) {\n        print(1)\n    }\n    break\n}\nlet after = 2\n",
+        );
+        assert_eq!(
+            messages(&errors),
+            vec!["test.txt:2:12: Expected expression, found `)`"]
+        );
+        assert_eq!(ast.len(), 2);
+        let Ast::While(_, body, _, _) = &ast[0] else {
+            panic!("expected the loop, got {:?}", ast[0]);
+        };
+        assert!(matches!(&body[..], [Ast::Break(None)]));
+        assert!(matches!(&ast[1], Ast::Var(name, Some(_), _) if name == "after"));
+    }
+
+    #[test]
+    fn reportsBadStatementsInsideInterpolations() {
+        let (_, errors) = parse("print(\"v={(func () { )( finished 2 })()}\")\n");
+        assert_eq!(
+            messages(&errors),
+            vec!["test.txt:1:22: Expected expression, found `)`"]
+        );
+    }
+
+    // the expression `let x = ...` is set to, with every binary operation in parentheses
+    fn grouped(expression: &str) -> String {
+        fn render(ast: &Ast) -> String {
+            match ast {
+                Ast::Binary(left, op, right, _) => {
+                    // operators serialize as the symbol they're written with
+                    let op = serde_json::to_value(op).unwrap();
+                    format!(
+                        "({} {} {})",
+                        render(left),
+                        op.as_str().unwrap(),
+                        render(right)
+                    )
+                }
+                Ast::Var(name, None, _) => name.clone(),
+                Ast::Literal(Literal {
+                    content: TokenContentType::Number(n),
+                }) => n.to_string(),
+                _ => panic!("unexpected {:?}", ast),
+            }
+        }
+
+        let (ast, errors) = parse(&format!("let x = {}", expression));
+        assert!(errors.is_empty(), "{:?}", messages(&errors));
+        let [Ast::Var(_, Some(value), _)] = &ast[..] else {
+            panic!("expected one declaration, got {:?}", ast);
+        };
+        render(value)
+    }
+
+    #[test]
+    fn operatorsGroupByPrecedenceAndAssociativity() {
+        assert_eq!(grouped("a - b - c"), "((a - b) - c)");
+        assert_eq!(grouped("a / b * c"), "((a / b) * c)");
+        assert_eq!(grouped("2 ** 3 ** 2"), "(2 ** (3 ** 2))");
+        assert_eq!(grouped("a * b ** c"), "(a * (b ** c))");
+        // each level binds tighter than the one before it
+        assert_eq!(
+            grouped("a || b && c == d < e + f * g"),
+            "(a || (b && (c == (d < (e + (f * g))))))"
+        );
+        assert_eq!(
+            grouped("a * b + c < d == e && f || g"),
+            "((((((a * b) + c) < d) == e) && f) || g)"
+        );
+        assert_eq!(grouped("a | b ^ c & d << e"), "(a | (b ^ (c & (d << e))))");
+    }
+
+    #[test]
+    fn jumpsNeedALoopToLeave() {
+        let (_, errors) = parse(
+            "break\nwhile (true) {\n    let f = func () {\n        continue\n    }\n}\nouter: while (true) {\n    break inner\n}\n",
+        );
+        assert_eq!(
+            messages(&errors),
+            vec![
+                "test.txt:1:1: Expected a loop around `break`, found `break` outside of any loop",
+                // a function body starts outside every loop, even a lambda written inside one
+                "test.txt:4:9: Expected a loop around `continue`, found `continue` outside of any loop",
+                "test.txt:8:11: Expected label of an enclosing loop, found identifier `inner`",
+            ]
+        );
+    }
+
+    #[test]
+    fn positionalArgumentsComeFirst() {
+        let (_, errors) = parse("f(a: 1, 2)\n");
+        assert_eq!(
+            messages(&errors),
+            vec!["test.txt:1:9: Expected named argument, found `2`"]
+        );
+    }
+
+    #[test]
+    fn validProgramHasNoErrors() {
+        let (ast, errors) = parse("let x = [1, 2]\nx[0] += 1\nprint(x)\n");
+        assert!(errors.is_empty(), "{:?}", messages(&errors));
+        assert_eq!(ast.len(), 3);
+    }
+}
@@ -4,3 +4,28 @@ macro_rules! ink {
         print!($($arg)*);
     };
 }
+
+thread_local! {
+    static SEED: std::cell::Cell<u64> = std::cell::Cell::new(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0x2545_f491_4f6c_dd1d, |d| d.as_nanos() as u64)
+            | 1,
+    );
+}
+
+/**
+ * A uniformly distributed number in `[min, max)`, from a time-seeded xorshift generator.
+ * Good enough for sketches, not for anything that needs real randomness.
+ */
+pub fn random(min: f64, max: f64) -> f64 {
+    let bits = SEED.with(|seed| {
+        let mut x = seed.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        seed.set(x);
+        x
+    });
+    min + (bits >> 11) as f64 / (1u64 << 53) as f64 * (max - min)
+}
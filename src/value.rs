@@ -0,0 +1,713 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{Ast, Param},
+    environment::Scope,
+    interpreter::{ErrorKind, RuntimeError},
+    lexer::TokenContentType,
+};
+
+/**
+ * Something a program computes while it runs. The parser only ever builds Ast; the interpreter
+ * turns it into these, and everything it stores in variables, passes to functions or prints is
+ * a Value.
+ *
+ * Numbers, strings, booleans and None are copied around. Arrays and instances live on the heap
+ * and a Value only points at one, so every variable, element or argument holding the same array
+ * sees changes made through any of them; `copy` and `deepCopy` make a separate one.
+ */
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    None,
+    Array(Rc<RefCell<Vec<Value>>>),
+    Instance(Rc<RefCell<Instance>>),
+    Function(Rc<Function>),
+    NativeFunction(Rc<NativeFunction>),
+}
+
+/**
+ * A `prep`ared struct, with its fields in the order the struct declares them.
+ */
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Instance {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value)
+    }
+
+    pub fn get_mut(&mut self, field: &str) -> Option<&mut Value> {
+        self.fields
+            .iter_mut()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value)
+    }
+}
+
+/**
+ * A function written in the language, named or a lambda, along with the environment it was
+ * defined in so its body can see the variables around it.
+ */
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Vec<Ast>,
+    pub closure: Scope,
+}
+
+// a builtin, written in Rust
+pub struct NativeFunction {
+    pub name: String,
+    pub function: Box<dyn Fn(Vec<Value>) -> Result<Value, RuntimeError>>,
+}
+
+// the closure usually holds the function itself, so it isn't printed
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<func {}>", self.name)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native func {}>", self.name)
+    }
+}
+
+impl Value {
+    pub fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn instance(name: &str, fields: Vec<(String, Value)>) -> Value {
+        Value::Instance(Rc::new(RefCell::new(Instance {
+            name: name.to_string(),
+            fields,
+        })))
+    }
+
+    // a new array or instance holding the same elements or field values as this one
+    pub fn copy(&self) -> Value {
+        match self {
+            Value::Array(items) => Value::array(items.borrow().clone()),
+            Value::Instance(instance) => {
+                Value::Instance(Rc::new(RefCell::new(instance.borrow().clone())))
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // like `copy`, but the arrays and instances inside are copied too, all the way down
+    pub fn deepCopy(&self) -> Value {
+        self.deepCopyInto(&mut HashMap::new())
+    }
+
+    /**
+     * `deepCopy`, with the copies made so far by the address of the original. An array or
+     * instance met again gets the same copy, so ones shared inside the original are shared in
+     * the copy, and one that contains itself is only copied once.
+     */
+    fn deepCopyInto(&self, copies: &mut HashMap<*const (), Value>) -> Value {
+        if let Some(copy) = self.address().and_then(|address| copies.get(&address)) {
+            return copy.clone();
+        }
+        match self {
+            Value::Array(items) => {
+                let copy = Rc::new(RefCell::new(vec![]));
+                copies.insert(
+                    Rc::as_ptr(items) as *const (),
+                    Value::Array(Rc::clone(&copy)),
+                );
+                let copied = items
+                    .borrow()
+                    .iter()
+                    .map(|item| item.deepCopyInto(copies))
+                    .collect();
+                *copy.borrow_mut() = copied;
+                Value::Array(copy)
+            }
+            Value::Instance(instance) => {
+                let copy = Rc::new(RefCell::new(Instance {
+                    name: instance.borrow().name.clone(),
+                    fields: vec![],
+                }));
+                copies.insert(
+                    Rc::as_ptr(instance) as *const (),
+                    Value::Instance(Rc::clone(&copy)),
+                );
+                let fields = instance
+                    .borrow()
+                    .fields
+                    .iter()
+                    .map(|(field, value)| (field.clone(), value.deepCopyInto(copies)))
+                    .collect();
+                copy.borrow_mut().fields = fields;
+                Value::Instance(copy)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // where the array or instance this points at lives, to recognise it when it comes round again
+    fn address(&self) -> Option<*const ()> {
+        match self {
+            Value::Array(items) => Some(Rc::as_ptr(items) as *const ()),
+            Value::Instance(instance) => Some(Rc::as_ptr(instance) as *const ()),
+            _ => None,
+        }
+    }
+
+    pub fn native(
+        name: &str,
+        function: impl Fn(Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) -> Value {
+        Value::NativeFunction(Rc::new(NativeFunction {
+            name: name.to_string(),
+            function: Box::new(function),
+        }))
+    }
+
+    // what kind of value this is, for error messages
+    pub fn typeName(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "boolean",
+            Value::None => "None",
+            Value::Array(_) => "array",
+            Value::Instance(_) => "instance",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+        }
+    }
+
+    fn numbers(self, other: Self, operation: &str) -> Result<(f64, f64), RuntimeError> {
+        match (self, other) {
+            (Value::Number(n), Value::Number(other_n)) => Ok((n, other_n)),
+            (left, right) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!(
+                    "Expected numbers for {} but got {} and {}",
+                    operation,
+                    left.typeName(),
+                    right.typeName()
+                ),
+            )),
+        }
+    }
+
+    // bitwise operators work on whole numbers only
+    fn integers(self, other: Self, operation: &str) -> Result<(i64, i64), RuntimeError> {
+        let (n, other_n) = self.numbers(other, operation)?;
+        if n.fract() != 0.0 || other_n.fract() != 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!(
+                    "Expected whole numbers for {} but got {} and {}",
+                    operation, n, other_n
+                ),
+            ));
+        }
+        Ok((n as i64, other_n as i64))
+    }
+
+    fn shift(amount: i64) -> Result<u32, RuntimeError> {
+        if !(0..64).contains(&amount) {
+            return Err(RuntimeError::new(
+                ErrorKind::Arithmetic,
+                format!("Cannot shift by {}", amount),
+            ));
+        }
+        Ok(amount as u32)
+    }
+
+    // prefix `+`, which only checks that it's given a number
+    pub fn positive(self) -> Result<Value, RuntimeError> {
+        match self {
+            Value::Number(_) => Ok(self),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Expected number for + but got {}", self.typeName()),
+            )),
+        }
+    }
+
+    pub fn pow(self, other: Self) -> Result<Value, RuntimeError> {
+        let (n, other_n) = self.numbers(other, "**")?;
+        Ok(Value::Number(n.powf(other_n)))
+    }
+
+    pub fn floorDiv(self, other: Self) -> Result<Value, RuntimeError> {
+        let (n, other_n) = self.numbers(other, "//")?;
+        if other_n == 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::Arithmetic,
+                "Cannot divide by zero",
+            ));
+        }
+        Ok(Value::Number((n / other_n).floor()))
+    }
+
+    /**
+     * How `<`, `<=`, `>` and `>=` order two values, which have to be numbers, strings, booleans
+     * or arrays of those, the same type on both sides. None only for NaN, which isn't ordered
+     * against anything, so every comparison with it is false.
+     */
+    pub fn order(&self, other: &Value, operation: &str) -> Result<Option<Ordering>, RuntimeError> {
+        match (self.partial_cmp(other), self, other) {
+            (Some(ordering), _, _) => Ok(Some(ordering)),
+            (None, Value::Number(_), Value::Number(_)) => Ok(None),
+            (None, Value::Array(_), Value::Array(_)) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!(
+                    "Cannot compare arrays with different element types using {}",
+                    operation
+                ),
+            )),
+            (None, left, right) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!(
+                    "Cannot compare {} and {} using {}",
+                    left.typeName(),
+                    right.typeName(),
+                    operation
+                ),
+            )),
+        }
+    }
+}
+
+impl From<TokenContentType> for Value {
+    fn from(content: TokenContentType) -> Self {
+        match content {
+            TokenContentType::Number(n) => Value::Number(n),
+            TokenContentType::String(s) => Value::String(s),
+            TokenContentType::Boolean(b) => Value::Bool(b),
+            TokenContentType::Interpolation(_) => {
+                unreachable!("interpolated strings are parsed into Ast::Interpolation")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut vec![])
+    }
+}
+
+/**
+ * Arrays and instances can end up inside themselves, so printing and comparing them keeps track
+ * of the ones it's already inside of rather than following the loop forever.
+ */
+impl Value {
+    // prints an array or instance that's already being printed as `[...]` or `Name {...}`
+    fn write(&self, f: &mut fmt::Formatter, inside: &mut Vec<*const ()>) -> fmt::Result {
+        let address = self.address();
+        if let Some(address) = address {
+            if inside.contains(&address) {
+                return match self {
+                    Value::Instance(instance) => write!(f, "{} {{...}}", instance.borrow().name),
+                    _ => write!(f, "[...]"),
+                };
+            }
+            inside.push(address);
+        }
+        match self {
+            Value::Number(n) => write!(f, "{}", n)?,
+            Value::String(s) => write!(f, "{}", s)?,
+            Value::Bool(b) => write!(f, "{}", b)?,
+            Value::None => write!(f, "None")?,
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write(f, inside)?;
+                }
+                write!(f, "]")?
+            }
+            Value::Instance(instance) => {
+                let instance = instance.borrow();
+                write!(f, "{} {{", instance.name)?;
+                for (i, (field, value)) in instance.fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", field)?;
+                    value.write(f, inside)?;
+                }
+                write!(f, "}}")?
+            }
+            Value::Function(function) => write!(f, "<func {}>", function.name)?,
+            Value::NativeFunction(function) => write!(f, "<native func {}>", function.name)?,
+        }
+        if address.is_some() {
+            inside.pop();
+        }
+        Ok(())
+    }
+
+    /**
+     * `==`, with the pairs of arrays or instances being compared further out. Coming back to
+     * one of those pairs means both sides loop the same way, and nothing along the loop
+     * differed, so they count as equal.
+     */
+    fn equals(&self, other: &Value, comparing: &mut Vec<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Value::Number(n), Value::Number(other_n)) => n == other_n,
+            (Value::String(s), Value::String(other_s)) => s == other_s,
+            (Value::Bool(b), Value::Bool(other_b)) => b == other_b,
+            (Value::None, Value::None) => true,
+            // arrays and instances are equal when their contents are, shared or not
+            (Value::Array(items), Value::Array(other_items)) => {
+                if Rc::ptr_eq(items, other_items) {
+                    return true;
+                }
+                let pair = (
+                    Rc::as_ptr(items) as *const (),
+                    Rc::as_ptr(other_items) as *const (),
+                );
+                if comparing.contains(&pair) {
+                    return true;
+                }
+                comparing.push(pair);
+                let (items, other_items) = (items.borrow(), other_items.borrow());
+                let equal = items.len() == other_items.len()
+                    && items
+                        .iter()
+                        .zip(other_items.iter())
+                        .all(|(item, other_item)| item.equals(other_item, comparing));
+                comparing.pop();
+                equal
+            }
+            (Value::Instance(instance), Value::Instance(other_instance)) => {
+                if Rc::ptr_eq(instance, other_instance) {
+                    return true;
+                }
+                let pair = (
+                    Rc::as_ptr(instance) as *const (),
+                    Rc::as_ptr(other_instance) as *const (),
+                );
+                if comparing.contains(&pair) {
+                    return true;
+                }
+                comparing.push(pair);
+                let (instance, other_instance) = (instance.borrow(), other_instance.borrow());
+                let equal = instance.name == other_instance.name
+                    && instance.fields.len() == other_instance.fields.len()
+                    && instance
+                        .fields
+                        .iter()
+                        .zip(other_instance.fields.iter())
+                        .all(|((field, value), (other_field, other_value))| {
+                            field == other_field && value.equals(other_value, comparing)
+                        });
+                comparing.pop();
+                equal
+            }
+            // functions are only equal to themselves
+            (Value::Function(function), Value::Function(other_function)) => {
+                Rc::ptr_eq(function, other_function)
+            }
+            (Value::NativeFunction(function), Value::NativeFunction(other_function)) => {
+                Rc::ptr_eq(function, other_function)
+            }
+            _ => false,
+        }
+    }
+
+    // ordering, looking out for loops the same way as `equals`
+    fn compare(
+        &self,
+        other: &Value,
+        comparing: &mut Vec<(*const (), *const ())>,
+    ) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(n), Value::Number(other_n)) => n.partial_cmp(other_n),
+            (Value::String(s), Value::String(other_s)) => s.partial_cmp(other_s),
+            (Value::Bool(b), Value::Bool(other_b)) => b.partial_cmp(other_b),
+            // element by element, then the shorter one first
+            (Value::Array(items), Value::Array(other_items)) => {
+                if Rc::ptr_eq(items, other_items) {
+                    return Some(Ordering::Equal);
+                }
+                let pair = (
+                    Rc::as_ptr(items) as *const (),
+                    Rc::as_ptr(other_items) as *const (),
+                );
+                if comparing.contains(&pair) {
+                    return Some(Ordering::Equal);
+                }
+                comparing.push(pair);
+                let (items, other_items) = (items.borrow(), other_items.borrow());
+                let mut ordering = Some(items.len().cmp(&other_items.len()));
+                for (item, other_item) in items.iter().zip(other_items.iter()) {
+                    match item.compare(other_item, comparing) {
+                        Some(Ordering::Equal) => {}
+                        unequal => {
+                            ordering = unequal;
+                            break;
+                        }
+                    }
+                }
+                comparing.pop();
+                ordering
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.equals(other, &mut vec![])
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.compare(other, &mut vec![])
+    }
+}
+
+impl Not for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Value::Bool(b) => Ok(Value::Bool(!b)),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Expected boolean for ! but got {}", self.typeName()),
+            )),
+        }
+    }
+}
+
+impl Neg for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            _ => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Expected number for - but got {}", self.typeName()),
+            )),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn add(self, other: Self) -> Self::Output {
+        // support strings and numbers
+        match (self, other) {
+            (Value::Number(n), Value::Number(other_n)) => Ok(Value::Number(n + other_n)),
+            (Value::String(s), Value::String(other_s)) => Ok(Value::String(s + &other_s)),
+            (left, right) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!("Cannot add {} and {}", left.typeName(), right.typeName()),
+            )),
+        }
+    }
+}
+
+impl Sub for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn sub(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.numbers(other, "-")?;
+        Ok(Value::Number(n - other_n))
+    }
+}
+
+impl Mul for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn mul(self, other: Self) -> Self::Output {
+        // numbers, or a string repeated a whole number of times
+        match (self, other) {
+            (Value::Number(n), Value::Number(other_n)) => Ok(Value::Number(n * other_n)),
+            (Value::String(s), Value::Number(other_n)) => {
+                if other_n.fract() != 0.0 || other_n < 0.0 {
+                    return Err(RuntimeError::new(
+                        ErrorKind::Arithmetic,
+                        format!("Cannot repeat a string {} times", other_n),
+                    ));
+                }
+                Ok(Value::String(s.repeat(other_n as usize)))
+            }
+            (left, right) => Err(RuntimeError::new(
+                ErrorKind::Type,
+                format!(
+                    "Cannot multiply {} by {}",
+                    left.typeName(),
+                    right.typeName()
+                ),
+            )),
+        }
+    }
+}
+
+impl Div for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn div(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.numbers(other, "/")?;
+        if other_n == 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::Arithmetic,
+                "Cannot divide by zero",
+            ));
+        }
+        Ok(Value::Number(n / other_n))
+    }
+}
+
+impl Rem for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn rem(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.numbers(other, "%")?;
+        if other_n == 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::Arithmetic,
+                "Cannot get remainder by zero",
+            ));
+        }
+        Ok(Value::Number(n % other_n))
+    }
+}
+
+impl BitAnd for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn bitand(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.integers(other, "&")?;
+        Ok(Value::Number((n & other_n) as f64))
+    }
+}
+
+impl BitOr for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn bitor(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.integers(other, "|")?;
+        Ok(Value::Number((n | other_n) as f64))
+    }
+}
+
+impl BitXor for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn bitxor(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.integers(other, "^")?;
+        Ok(Value::Number((n ^ other_n) as f64))
+    }
+}
+
+impl Shl for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn shl(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.integers(other, "<<")?;
+        Ok(Value::Number((n << Value::shift(other_n)?) as f64))
+    }
+}
+
+impl Shr for Value {
+    type Output = Result<Value, RuntimeError>;
+
+    fn shr(self, other: Self) -> Self::Output {
+        let (n, other_n) = self.integers(other, ">>")?;
+        Ok(Value::Number((n >> Value::shift(other_n)?) as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `[1, itself]`
+    fn selfContaining() -> Value {
+        let array = Value::array(vec![Value::Number(1.0)]);
+        if let Value::Array(items) = &array {
+            items.borrow_mut().push(array.clone());
+        }
+        array
+    }
+
+    fn element(array: &Value, i: usize) -> Value {
+        match array {
+            Value::Array(items) => items.borrow()[i].clone(),
+            _ => panic!("expected an array, got {}", array),
+        }
+    }
+
+    fn same(value: &Value, other: &Value) -> bool {
+        value.address().is_some() && value.address() == other.address()
+    }
+
+    #[test]
+    fn printsCyclesOnce() {
+        assert_eq!(selfContaining().to_string(), "[1, [...]]");
+
+        let node = Value::instance("Node", vec![("next".to_string(), Value::None)]);
+        if let Value::Instance(instance) = &node {
+            *instance.borrow_mut().get_mut("next").unwrap() = node.clone();
+        }
+        assert_eq!(node.to_string(), "Node {next: Node {...}}");
+
+        // an array in two places that doesn't contain itself is printed in full both times
+        let inner = Value::array(vec![Value::Number(2.0)]);
+        let outer = Value::array(vec![inner.clone(), inner]);
+        assert_eq!(outer.to_string(), "[[2], [2]]");
+    }
+
+    #[test]
+    fn comparesCycles() {
+        assert!(selfContaining() == selfContaining());
+        assert_eq!(
+            selfContaining().partial_cmp(&selfContaining()),
+            Some(Ordering::Equal)
+        );
+        let longer = selfContaining();
+        if let Value::Array(items) = &longer {
+            items.borrow_mut().push(Value::Number(3.0));
+        }
+        assert!(selfContaining() != longer);
+    }
+
+    #[test]
+    fn deepCopyKeepsCyclesAndSharing() {
+        let original = selfContaining();
+        let copy = original.deepCopy();
+        assert!(!same(&copy, &original));
+        assert!(same(&element(&copy, 1), &copy));
+
+        let inner = Value::array(vec![]);
+        let pair = Value::array(vec![inner.clone(), inner]);
+        let copy = pair.deepCopy();
+        assert!(same(&element(&copy, 0), &element(&copy, 1)));
+        assert!(!same(&element(&copy, 0), &element(&pair, 0)));
+    }
+}